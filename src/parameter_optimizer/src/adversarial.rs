@@ -0,0 +1,147 @@
+//! Searching for the attacks the swarm is weakest against. The swarm parameters stay fixed by the
+//! scenario while the optimizer picks the attack, looking for the largest formation error
+
+use crate::parameter::{Parameter, ParameterType};
+
+use indexmap::IndexMap;
+
+/// The attacks the simulation knows, apart from `none`
+pub const ATTACKS: [&str; 3] = ["takeover", "jamming", "spoofing"];
+
+/// How many of the most damaging attacks are reported
+pub const REPORTED_ATTACKS: usize = 10;
+
+/// Settings of an attack campaign
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Adversarial {
+    /// The kind of attack whose parameters are searched
+    pub attack: String,
+}
+
+impl Adversarial {
+    pub fn new(attack: &str) -> Result<Self, crate::Error> {
+        if !ATTACKS.contains(&attack) {
+            return Err(format!("Unknown attack {}, expected one of {:?}", attack, ATTACKS).into());
+        }
+        Ok(Self {
+            attack: attack.to_owned(),
+        })
+    }
+
+    /// The attack parameters searched when none are given: which node is compromised, when the
+    /// attack starts, how long it lasts and how strong it is. Jamming hits every node and a
+    /// takeover has no strength, so those leave out the parameter that does nothing
+    pub fn parameters(
+        &self,
+        scenario: &IndexMap<String, String>,
+        full_duration: f64,
+    ) -> Result<Vec<Parameter>, crate::Error> {
+        let peripheral_nodes: f64 = scenario
+            .get("pNodes")
+            .ok_or("The scenario has no pNodes")?
+            .parse()?;
+        let mut params = Vec::new();
+        if self.attack != "jamming" {
+            // Addresses end in 1 for the central node and count up from there. Only peripheral
+            // nodes are attacked, since a taken over node claims to be the central node
+            if peripheral_nodes < 1.0 {
+                return Err("The scenario has no peripheral nodes to attack".into());
+            }
+            // A single peripheral node leaves an integer range with nothing in it
+            let kind = if peripheral_nodes < 2.0 {
+                ParameterType::Categorical(vec![2.0])
+            } else {
+                ParameterType::Integer
+            };
+            params.push(Parameter {
+                kind,
+                ..Parameter::float("attackNode", 2.0, 1.0 + peripheral_nodes).with_default(2.0)
+            });
+        }
+        params.push(Parameter::float("attackStart", 0.0, full_duration).with_default(15.0));
+        params.push(Parameter::float("attackDuration", 1.0, full_duration));
+        if self.attack != "takeover" {
+            params.push(Parameter::float("attackIntensity", 0.0, 1.0).with_default(0.5));
+        }
+        Ok(params)
+    }
+}
+
+/// One of the most damaging attacks found
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct WorstAttack {
+    pub attack: String,
+    pub parameters: IndexMap<String, f64>,
+    /// The mean error of the swarm under this attack
+    pub formation_error: f64,
+    /// Half width of the 95% confidence interval of `formation_error`
+    pub confidence: f64,
+}
+
+/// Picks the `count` attacks with the largest formation error, worst first. Attacks whose
+/// simulations failed are skipped
+pub fn worst_attacks(mut attacks: Vec<WorstAttack>, count: usize) -> Vec<WorstAttack> {
+    attacks.retain(|a| !a.formation_error.is_nan());
+    attacks.sort_by(|a, b| b.formation_error.partial_cmp(&a.formation_error).unwrap());
+    attacks.truncate(count);
+    attacks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_parameters() {
+        let mut scenario = IndexMap::new();
+        scenario.insert("pNodes".to_owned(), "8".to_owned());
+        let names = |attack: &str| -> Vec<String> {
+            Adversarial::new(attack)
+                .unwrap()
+                .parameters(&scenario, 180.0)
+                .unwrap()
+                .into_iter()
+                .map(|p| p.name)
+                .collect()
+        };
+        assert_eq!(
+            names("spoofing"),
+            vec![
+                "attackNode",
+                "attackStart",
+                "attackDuration",
+                "attackIntensity"
+            ]
+        );
+        assert!(!names("jamming").contains(&"attackNode".to_owned()));
+        assert!(!names("takeover").contains(&"attackIntensity".to_owned()));
+
+        let spoofing = Adversarial::new("spoofing").unwrap();
+        let node = &spoofing.parameters(&scenario, 180.0).unwrap()[0];
+        assert_eq!(node.denormalize(0.0), 2.0);
+        assert_eq!(node.denormalize(1.0), 9.0);
+
+        scenario.insert("pNodes".to_owned(), "1".to_owned());
+        let node = &spoofing.parameters(&scenario, 180.0).unwrap()[0];
+        assert_eq!(node.denormalize(0.7), 2.0);
+        scenario.insert("pNodes".to_owned(), "0".to_owned());
+        assert!(spoofing.parameters(&scenario, 180.0).is_err());
+        assert!(Adversarial::new("none").is_err());
+    }
+
+    #[test]
+    fn reports_worst_first() {
+        let attack = |error: f64| WorstAttack {
+            attack: "jamming".to_owned(),
+            parameters: IndexMap::new(),
+            formation_error: error,
+            confidence: 0.0,
+        };
+        let errors: Vec<f64> = worst_attacks(vec![attack(2.0), attack(f64::NAN), attack(7.0)], 5)
+            .iter()
+            .map(|a| a.formation_error)
+            .collect();
+        assert_eq!(errors, vec![7.0, 2.0]);
+        assert_eq!(worst_attacks(vec![attack(2.0), attack(7.0)], 1).len(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+//! Limits on parameters and measured metrics that a run must respect to count as feasible, such
+//! as `r>2` or `peripheral_distance_mad<0.5`
+
+use indexmap::IndexMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Comparison {
+    #[serde(rename = "<")]
+    Less,
+    #[serde(rename = "<=")]
+    LessOrEqual,
+    #[serde(rename = ">")]
+    Greater,
+    #[serde(rename = ">=")]
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, value: f64, bound: f64) -> bool {
+        match self {
+            Comparison::Less => value < bound,
+            Comparison::LessOrEqual => value <= bound,
+            Comparison::Greater => value > bound,
+            Comparison::GreaterOrEqual => value >= bound,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+/// Requires the parameter or metric `name` to compare with `bound`. Constraints on parameters are
+/// checked before simulating, and constraints on metrics once every replicate is in
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Constraint {
+    pub name: String,
+    pub comparison: Comparison,
+    pub bound: f64,
+}
+
+impl FromStr for Constraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Two character operators first, so that `<=` isn't read as `<`
+        let comparisons = [
+            Comparison::LessOrEqual,
+            Comparison::GreaterOrEqual,
+            Comparison::Less,
+            Comparison::Greater,
+        ];
+        let (name, comparison, bound) = comparisons
+            .iter()
+            .find_map(|c| {
+                s.split_once(c.symbol())
+                    .map(|(name, bound)| (name.trim(), *c, bound.trim()))
+            })
+            .ok_or_else(|| format!("Expected NAME<VALUE or NAME>VALUE, got `{}`", s))?;
+        if name.is_empty() {
+            return Err(format!("Missing the name in `{}`", s));
+        }
+        let bound = bound
+            .parse::<f64>()
+            .map_err(|err| format!("Bad bound in `{}`: {}", s, err))?;
+        Ok(Self {
+            name: name.to_owned(),
+            comparison,
+            bound,
+        })
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.name, self.comparison.symbol(), self.bound)
+    }
+}
+
+impl Constraint {
+    /// Returns why `values` break this constraint, or `None` if they don't. A value that was not
+    /// measured, or is NaN, breaks it
+    pub fn violation(&self, values: &IndexMap<String, f64>) -> Option<String> {
+        match values.get(&self.name) {
+            Some(value) if self.comparison.holds(*value, self.bound) => None,
+            Some(value) => Some(format!("{}={} breaks {}", self.name, value, self)),
+            None => Some(format!("{} was not measured for {}", self.name, self)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_check() {
+        let constraint: Constraint = "mean_velocity <= 0.5".parse().unwrap();
+        assert_eq!(constraint.comparison, Comparison::LessOrEqual);
+        assert_eq!(constraint.to_string(), "mean_velocity<=0.5");
+        assert!("r=2".parse::<Constraint>().is_err());
+        assert!(">2".parse::<Constraint>().is_err());
+        assert!("r>two".parse::<Constraint>().is_err());
+
+        let mut values = IndexMap::new();
+        values.insert("mean_velocity".to_owned(), 0.5);
+        assert_eq!(constraint.violation(&values), None);
+        values["mean_velocity"] = 0.7;
+        assert!(constraint.violation(&values).is_some());
+        values["mean_velocity"] = f64::NAN;
+        assert!(constraint.violation(&values).is_some());
+        let r: Constraint = "r>2".parse().unwrap();
+        assert!(r.violation(&values).unwrap().contains("not measured"));
+    }
+}
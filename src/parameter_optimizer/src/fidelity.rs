@@ -0,0 +1,215 @@
+//! Multi-fidelity scheduling: candidates are screened with short simulations and only the best
+//! ones are simulated for longer
+
+/// Asynchronous successive halving over simulation durations.
+///
+/// Every rung simulates for `reduction` times longer than the rung below it, up to the full
+/// duration. Whenever a runner needs work, the best `1 / reduction` of the candidates evaluated in
+/// a rung are promoted to the next rung. If none can be promoted a new candidate starts in the
+/// bottom rung, or with `hyperband` in a rung picked so that every rung is started from about as
+/// often as Hyperband's brackets would
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SuccessiveHalving {
+    /// Simulation duration of each rung in seconds, shortest first
+    durations: Vec<f64>,
+    reduction: usize,
+    hyperband: bool,
+    /// Every candidate that has been started, as points in the optimizer's unit hypercube
+    candidates: Vec<Vec<f64>>,
+    /// The candidates evaluated in each rung and their errors
+    rungs: Vec<Vec<(usize, f64)>>,
+    /// Position in the cycle of starting rungs used by `hyperband`
+    next_bracket: usize,
+    /// Candidates that are being evaluated and the rung they are in
+    #[serde(skip)]
+    in_flight: Vec<(usize, usize)>,
+}
+
+/// A candidate to simulate and the rung to simulate it in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trial {
+    pub candidate: usize,
+    pub rung: usize,
+    pub point: Vec<f64>,
+    pub duration: f64,
+}
+
+impl SuccessiveHalving {
+    /// Creates rungs from `min_duration` up to `full_duration`, each `reduction` times longer
+    /// than the one before
+    pub fn new(
+        min_duration: f64,
+        full_duration: f64,
+        reduction: usize,
+        hyperband: bool,
+    ) -> Result<Self, crate::Error> {
+        if reduction < 2 {
+            return Err("The reduction factor must be at least 2".into());
+        }
+        if !(min_duration > 0.0 && min_duration < full_duration) {
+            return Err(format!(
+                "The shortest duration must be between 0 and {} seconds",
+                full_duration
+            )
+            .into());
+        }
+        let mut durations = Vec::new();
+        let mut duration = min_duration;
+        while duration < full_duration {
+            durations.push(duration);
+            duration *= reduction as f64;
+        }
+        durations.push(full_duration);
+
+        Ok(Self {
+            rungs: vec![Vec::new(); durations.len()],
+            durations,
+            reduction,
+            hyperband,
+            candidates: Vec::new(),
+            next_bracket: 0,
+            in_flight: Vec::new(),
+        })
+    }
+
+    pub fn durations(&self) -> &[f64] {
+        &self.durations
+    }
+
+    /// Returns true if `rung` simulates for the full duration
+    pub fn is_full(&self, rung: usize) -> bool {
+        rung + 1 == self.durations.len()
+    }
+
+    fn trial(&mut self, candidate: usize, rung: usize) -> Trial {
+        self.in_flight.push((candidate, rung));
+        Trial {
+            candidate,
+            rung,
+            point: self.candidates[candidate].clone(),
+            duration: self.durations[rung],
+        }
+    }
+
+    /// Returns the next candidate to simulate, starting a new one with `ask` if none are ready
+    /// to be promoted
+    pub fn next(
+        &mut self,
+        ask: impl FnOnce() -> Result<Vec<f64>, crate::Error>,
+    ) -> Result<Trial, crate::Error> {
+        // Prefer promotions from the top so that good candidates reach full length quickly
+        for rung in (0..self.durations.len() - 1).rev() {
+            if let Some(candidate) = self.promotable(rung) {
+                return Ok(self.trial(candidate, rung + 1));
+            }
+        }
+
+        self.candidates.push(ask()?);
+        let start = self.starting_rung();
+        Ok(self.trial(self.candidates.len() - 1, start))
+    }
+
+    /// Returns a candidate in the top `1 / reduction` of `rung` which has not been started in the
+    /// rung above
+    fn promotable(&self, rung: usize) -> Option<usize> {
+        let mut evaluated = self.rungs[rung].clone();
+        evaluated.sort_by(|a, b| error_order(a.1, b.1));
+        let top = evaluated.len() / self.reduction;
+        evaluated
+            .iter()
+            .take(top)
+            .map(|(candidate, _)| *candidate)
+            .find(|candidate| {
+                !self.rungs[rung + 1].iter().any(|(c, _)| c == candidate)
+                    && !self.in_flight.contains(&(*candidate, rung + 1))
+            })
+    }
+
+    /// The rung new candidates start in. With `s` rungs, Hyperband starts
+    /// `ceil(s / (s - b) * reduction^(s - b - 1))` candidates in bracket `b`, so the brackets are
+    /// cycled through in those proportions
+    fn starting_rung(&mut self) -> usize {
+        if !self.hyperband {
+            return 0;
+        }
+        let s = self.durations.len();
+        let schedule: Vec<usize> = (0..s)
+            .flat_map(|b| {
+                let count = (s as f64 / (s - b) as f64
+                    * (self.reduction as f64).powi((s - b - 1) as i32))
+                .ceil() as usize;
+                (0..count).map(move |_| b)
+            })
+            .collect();
+        let rung = schedule[self.next_bracket % schedule.len()];
+        self.next_bracket = (self.next_bracket + 1) % schedule.len();
+        rung
+    }
+
+    /// Records the error of a finished trial. Failed trials should be recorded with a NaN error
+    /// so that they are never promoted
+    pub fn record(&mut self, trial: &Trial, error: f64) {
+        self.in_flight
+            .retain(|in_flight| *in_flight != (trial.candidate, trial.rung));
+        self.rungs[trial.rung].push((trial.candidate, error));
+    }
+}
+
+/// Orders errors from best to worst with NaN last
+fn error_order(a: f64, b: f64) -> std::cmp::Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.partial_cmp(&b).unwrap(),
+        (a, b) => a.cmp(&b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rung_durations() {
+        let halving = SuccessiveHalving::new(20.0, 180.0, 3, false).unwrap();
+        assert_eq!(halving.durations(), &[20.0, 60.0, 180.0]);
+        let halving = SuccessiveHalving::new(10.0, 180.0, 3, false).unwrap();
+        assert_eq!(halving.durations(), &[10.0, 30.0, 90.0, 180.0]);
+        assert!(halving.is_full(3));
+
+        assert!(SuccessiveHalving::new(180.0, 180.0, 3, false).is_err());
+        assert!(SuccessiveHalving::new(20.0, 180.0, 1, false).is_err());
+    }
+
+    #[test]
+    fn promotes_best_third() {
+        let mut halving = SuccessiveHalving::new(20.0, 180.0, 3, false).unwrap();
+        let mut next_point = 0.0;
+        let mut ask = || {
+            next_point += 0.1;
+            Ok(vec![next_point])
+        };
+
+        let trials: Vec<Trial> = (0..3).map(|_| halving.next(&mut ask).unwrap()).collect();
+        assert!(trials.iter().all(|t| t.rung == 0 && t.duration == 20.0));
+        halving.record(&trials[0], 5.0);
+        halving.record(&trials[1], 1.0);
+        halving.record(&trials[2], f64::NAN);
+
+        // The best of the three moves up a rung, and only once
+        let promoted = halving.next(&mut ask).unwrap();
+        assert_eq!((promoted.candidate, promoted.rung), (1, 1));
+        assert_eq!(promoted.duration, 60.0);
+        let fresh = halving.next(&mut ask).unwrap();
+        assert_eq!((fresh.candidate, fresh.rung), (3, 0));
+    }
+
+    #[test]
+    fn hyperband_brackets() {
+        let mut halving = SuccessiveHalving::new(20.0, 180.0, 3, true).unwrap();
+        let mut counts = [0; 3];
+        for _ in 0..(9 + 5 + 3) {
+            let trial = halving.next(|| Ok(vec![0.5])).unwrap();
+            counts[trial.rung] += 1;
+        }
+        assert_eq!(counts, [9, 5, 3]);
+    }
+}
@@ -0,0 +1,421 @@
+//! Scoring a finished simulation. Each metric measures one thing about the trajectories of the
+//! swarm, and the configured weighted sum of them is the error the optimizer minimizes
+
+mod connectivity;
+mod convergence;
+mod formation;
+mod safety;
+
+pub use connectivity::{Connectivity, CONNECTIVITY_TERMS};
+pub use convergence::{Convergence, CONVERGENCE_TERMS};
+pub use formation::{CentralDistance, PeripheralSpacing, Velocity};
+pub use safety::{Separation, SEPARATION_TERMS};
+
+use crate::position_parser::{SimulationData, TimePoint, UavId, Vec3};
+
+use indexmap::IndexMap;
+use std::str::FromStr;
+
+/// The metrics that can be weighted or recorded, by name
+pub const METRICS: [&str; 16] = [
+    "peripheral_distance_mad",
+    "central_distance_deviation",
+    "mean_velocity",
+    "separation_shortfall",
+    "separation_violations",
+    "separation_violation_time",
+    "central_rise_time",
+    "central_settling_time",
+    "central_overshoot",
+    "central_steady_state_error",
+    "spacing_rise_time",
+    "spacing_settling_time",
+    "spacing_overshoot",
+    "disconnected_fraction",
+    "extra_components",
+    "max_isolated_time",
+];
+
+/// What a fitness function measured in a simulation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Breakdown {
+    /// The value of each metric, by name. These are the objectives of multi-objective
+    /// optimization, in this order
+    pub terms: IndexMap<String, f64>,
+    /// What each term adds to `total`, by name
+    pub costs: IndexMap<String, f64>,
+    /// Values the terms were computed from, kept to tell why a simulation scored as it did.
+    /// They are not optimized
+    pub metrics: IndexMap<String, f64>,
+    /// The error of the simulation. Lower is better
+    pub total: f64,
+}
+
+impl Breakdown {
+    /// A breakdown of a single metric whose value is the total
+    pub fn single(name: &str, value: f64) -> Self {
+        let mut terms = IndexMap::new();
+        terms.insert(name.to_owned(), value);
+        Self {
+            costs: terms.clone(),
+            terms,
+            metrics: IndexMap::new(),
+            total: value,
+        }
+    }
+
+    /// A breakdown of the value called `term` in `values`, keeping the others as metrics. For
+    /// fitness functions that measure several related things at once
+    pub fn select(term: &str, values: &[(&str, f64)]) -> Self {
+        let value = values
+            .iter()
+            .find(|(name, _)| *name == term)
+            .map_or(f64::NAN, |(_, value)| *value);
+        let mut breakdown = Self::single(term, value);
+        for (name, value) in values.iter().filter(|(name, _)| *name != term) {
+            breakdown = breakdown.with_metric(name, *value);
+        }
+        breakdown
+    }
+
+    /// Adds a value the terms were computed from
+    pub fn with_metric(mut self, name: &str, value: f64) -> Self {
+        self.metrics.insert(name.to_owned(), value);
+        self
+    }
+}
+
+/// Scores the trajectories of a finished simulation
+pub trait Fitness {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown;
+}
+
+/// The sum of other fitness functions, each multiplied by its weight
+#[derive(Default)]
+pub struct Weighted {
+    components: Vec<(f64, Box<dyn Fitness>)>,
+    /// Evaluated for their metrics only
+    recorded: Vec<Box<dyn Fitness>>,
+}
+
+impl Weighted {
+    pub fn add(&mut self, weight: f64, fitness: Box<dyn Fitness>) {
+        self.components.push((weight, fitness));
+    }
+
+    /// Keeps every value `fitness` measures as a metric, without adding it to the error
+    pub fn record(&mut self, fitness: Box<dyn Fitness>) {
+        self.recorded.push(fitness);
+    }
+}
+
+impl Fitness for Weighted {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let mut breakdown = Breakdown::default();
+        for (weight, fitness) in &self.components {
+            let component = fitness.evaluate(data);
+            breakdown.total += weight * component.total;
+            breakdown.terms.extend(component.terms);
+            breakdown.costs.extend(
+                component
+                    .costs
+                    .into_iter()
+                    .map(|(name, cost)| (name, weight * cost)),
+            );
+            breakdown.metrics.extend(component.metrics);
+        }
+        for fitness in &self.recorded {
+            let recorded = fitness.evaluate(data);
+            breakdown.metrics.extend(recorded.terms);
+            breakdown.metrics.extend(recorded.metrics);
+        }
+        breakdown
+    }
+}
+
+/// A metric and how much it counts towards the error, given as `NAME=WEIGHT`
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricWeight {
+    pub name: String,
+    pub weight: f64,
+}
+
+impl FromStr for MetricWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, weight) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected NAME=WEIGHT, got `{}`", s))?;
+        let weight = weight
+            .parse::<f64>()
+            .map_err(|err| format!("Bad weight in `{}`: {}", s, err))?;
+        Ok(Self {
+            name: name.to_owned(),
+            weight,
+        })
+    }
+}
+
+/// How simulations are scored. The defaults give the error the optimizer has always used
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FitnessConfig {
+    /// Seconds between the points in time the trajectories are sampled at
+    pub time_step: f32,
+    /// The node the others form a ring around. The one with the lowest address when missing
+    pub central_node: Option<UavId>,
+    /// How far from the central node the peripheral nodes should be, in meters
+    pub target_distance: f64,
+    /// How much each metric counts towards the error, by name
+    pub weights: IndexMap<String, f64>,
+    /// Metrics that are measured and stored but not optimized, for constraints and graphs
+    pub record: Vec<String>,
+    /// UAVs closer than this many meters are in danger of colliding
+    pub separation_threshold: f64,
+    /// The formation has settled once it stays within this fraction of its target
+    pub settling_band: f64,
+    /// Seconds at the end of the simulation over which the steady state is measured
+    pub final_window: f64,
+    /// UAVs within this many meters of each other can communicate. The simulated channel reaches
+    /// every node, so this is only used to judge how connected the formation stays
+    pub communication_radius: f64,
+}
+
+impl Default for FitnessConfig {
+    fn default() -> Self {
+        let weights = [
+            ("peripheral_distance_mad", 400.0),
+            ("central_distance_deviation", 400.0),
+            ("mean_velocity", 250.0),
+        ]
+        .iter()
+        .map(|(name, weight)| (name.to_string(), *weight))
+        .collect();
+        Self {
+            time_step: 0.1,
+            central_node: None,
+            target_distance: 7.5,
+            weights,
+            record: Vec::new(),
+            separation_threshold: 1.0,
+            settling_band: 0.05,
+            final_window: 30.0,
+            communication_radius: 10.0,
+        }
+    }
+}
+
+impl FitnessConfig {
+    /// Replaces the default weights with `weights`, unless it is empty
+    pub fn with_weights(mut self, weights: Vec<MetricWeight>) -> Self {
+        if !weights.is_empty() {
+            self.weights = weights.into_iter().map(|w| (w.name, w.weight)).collect();
+        }
+        self
+    }
+
+    /// Creates the weighted sum of the configured metrics
+    pub fn build(&self) -> Result<Weighted, crate::Error> {
+        if self.time_step.is_nan() || self.time_step <= 0.0 {
+            return Err(format!("Time step {} is not positive", self.time_step).into());
+        }
+        if self.weights.is_empty() {
+            return Err("No metrics are weighted".into());
+        }
+        let unknown =
+            |name: &String| format!("Unknown metric {}, expected one of {:?}", name, METRICS);
+        let mut fitness = Weighted::default();
+        for (name, weight) in &self.weights {
+            fitness.add(*weight, self.metric(name).ok_or_else(|| unknown(name))?);
+        }
+        for name in &self.record {
+            fitness.record(self.metric(name).ok_or_else(|| unknown(name))?);
+        }
+        Ok(fitness)
+    }
+
+    /// Creates the metric called `name`, if there is one
+    fn metric(&self, name: &str) -> Option<Box<dyn Fitness>> {
+        match name {
+            "peripheral_distance_mad" => Some(Box::new(PeripheralSpacing {
+                time_step: self.time_step,
+                central_node: self.central_node,
+            })),
+            "central_distance_deviation" => Some(Box::new(CentralDistance {
+                time_step: self.time_step,
+                central_node: self.central_node,
+                target_distance: self.target_distance,
+            })),
+            "mean_velocity" => Some(Box::new(Velocity {
+                time_step: self.time_step,
+            })),
+            _ => {
+                let find = |terms: &[&'static str]| terms.iter().copied().find(|t| *t == name);
+                if let Some(term) = find(&SEPARATION_TERMS) {
+                    Some(Box::new(Separation {
+                        time_step: self.time_step,
+                        threshold: self.separation_threshold,
+                        term,
+                    }))
+                } else if let Some(term) = find(&CONNECTIVITY_TERMS) {
+                    Some(Box::new(Connectivity {
+                        time_step: self.time_step,
+                        radius: self.communication_radius,
+                        term,
+                    }))
+                } else {
+                    find(&CONVERGENCE_TERMS)
+                        .map(|term| Box::new(self.convergence(term)) as Box<dyn Fitness>)
+                }
+            }
+        }
+    }
+
+    /// The convergence metrics with `term` as their term
+    pub fn convergence(&self, term: &'static str) -> Convergence {
+        Convergence {
+            time_step: self.time_step,
+            central_node: self.central_node,
+            target_distance: self.target_distance,
+            settling_band: self.settling_band,
+            final_window: self.final_window,
+            term,
+        }
+    }
+}
+
+/// The positions of the UAVs at one point in time
+pub struct Sample {
+    pub time: f32,
+    /// Ordered by address
+    pub positions: IndexMap<UavId, Vec3>,
+}
+
+/// Samples the trajectories every `time_step` seconds from the start to the end of the simulation
+pub fn sample(data: &mut SimulationData, time_step: f32) -> Vec<Sample> {
+    data.rewind();
+    let mut uavs: Vec<UavId> = data.uavs.iter().copied().collect();
+    uavs.sort();
+    let mut samples = Vec::new();
+    let mut time = 0.0;
+    while time <= data.simulation_length {
+        let positions = uavs
+            .iter()
+            .filter_map(|uav| Some((*uav, data.pos_at_time(TimePoint(time), *uav)?)))
+            .collect();
+        samples.push(Sample { time, positions });
+        time += time_step;
+    }
+    samples
+}
+
+/// Returns `central_node`, or the node with the lowest address if it is missing
+pub fn central_node(data: &SimulationData, central_node: Option<UavId>) -> Option<UavId> {
+    central_node.or_else(|| data.uavs.iter().min().copied())
+}
+
+/// The mean of `values`, or 0 if there are none
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+/// The mean absolute deviation of `values` from their mean
+pub fn absolute_deviation(values: &[f64]) -> f64 {
+    let center = mean(values);
+    mean(
+        &values
+            .iter()
+            .map(|v| (v - center).abs())
+            .collect::<Vec<_>>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two peripheral nodes that start 6m and 10m from the central node and move towards it,
+    /// meeting the target distance from t=1
+    fn test_data() -> SimulationData {
+        SimulationData::parse(
+            r#"Time (s),IP Address, X (m), Y (m), Z (m)
+0,10.1.1.1,0,0,0,
+0,10.1.1.2,6,0,0,
+0,10.1.1.3,0,10,0,
+1,10.1.1.1,0,0,0,
+1,10.1.1.2,7.5,0,0,
+1,10.1.1.3,0,7.5,0,
+2,10.1.1.1,0,0,0,
+2,10.1.1.2,7.5,0,0,
+2,10.1.1.3,0,7.5,0,"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn weighted_metrics() {
+        let mut data = test_data();
+        let config = FitnessConfig::default();
+        let breakdown = config.build().unwrap().evaluate(&mut data);
+        assert_eq!(
+            breakdown.terms.keys().collect::<Vec<_>>(),
+            METRICS[..3].iter().collect::<Vec<_>>()
+        );
+        let expected: f64 = config
+            .weights
+            .iter()
+            .map(|(name, weight)| weight * breakdown.terms[name])
+            .sum();
+        assert!((breakdown.total - expected).abs() < 1e-9);
+        assert!((breakdown.costs.values().sum::<f64>() - breakdown.total).abs() < 1e-9);
+        assert_eq!(
+            breakdown.costs["mean_velocity"],
+            250.0 * breakdown.terms["mean_velocity"]
+        );
+        let central = breakdown.metrics["mean_central_distance"];
+        assert!((breakdown.terms["central_distance_deviation"] - (central - 7.5)).abs() < 1e-9);
+
+        // The peripheral nodes move 1.5m and 2.5m during the first second, then stay put. The
+        // 20 samples end just before t=2
+        let velocity = breakdown.terms["mean_velocity"];
+        assert!(
+            (velocity - 4.0 / 3.0 * 10.0 / 20.0).abs() < 1e-3,
+            "{}",
+            velocity
+        );
+        // Their mean distance from the central node starts at 8m and ends at 7.5m
+        let deviation = breakdown.terms["central_distance_deviation"];
+        assert!(deviation > 0.0 && deviation < 0.5, "{}", deviation);
+
+        let speed_only =
+            FitnessConfig::default().with_weights(vec!["mean_velocity=2".parse().unwrap()]);
+        let breakdown = speed_only.build().unwrap().evaluate(&mut data);
+        assert_eq!(breakdown.terms.len(), 1);
+        assert!((breakdown.total - 2.0 * velocity).abs() < 1e-9);
+
+        // Recorded metrics don't change the error
+        let recording = FitnessConfig {
+            record: vec!["separation_violations".to_owned()],
+            ..speed_only.clone()
+        };
+        let recorded = recording.build().unwrap().evaluate(&mut data);
+        assert_eq!(recorded.total, breakdown.total);
+        assert_eq!(recorded.terms, breakdown.terms);
+        assert_eq!(recorded.metrics["separation_violations"], 0.0);
+        assert!(recorded.metrics["min_separation"] > 5.0);
+
+        let unknown = FitnessConfig::default().with_weights(vec!["speed=1".parse().unwrap()]);
+        assert!(unknown.build().is_err());
+        let unknown = FitnessConfig {
+            record: vec!["speed".to_owned()],
+            ..FitnessConfig::default()
+        };
+        assert!(unknown.build().is_err());
+        assert!("mean_velocity".parse::<MetricWeight>().is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Metrics of the communication graph of the swarm, in which two UAVs are linked while they are
+//! within radio range of each other. The simulation's channel has a fixed signal strength so every
+//! node hears every other one, which makes the range an assumption of the analysis
+
+use super::{mean, sample, Breakdown, Fitness};
+use crate::position_parser::{SimulationData, Vec3};
+
+use nalgebra::{DMatrix, SymmetricEigen};
+
+/// The metrics this module computes. Each one can be the term of a [`Connectivity`], which keeps
+/// the others as metrics
+pub const CONNECTIVITY_TERMS: [&str; 3] = [
+    "disconnected_fraction",
+    "extra_components",
+    "max_isolated_time",
+];
+
+/// How well connected the proximity graph of the swarm stays, where UAVs within `radius` meters
+/// of each other are linked. The term is one of [`CONNECTIVITY_TERMS`], all of which are lower
+/// when the swarm stays better connected:
+/// - `disconnected_fraction`: the fraction of the time some UAV can't reach all the others
+/// - `extra_components`: the mean number of connected components beyond one
+/// - `max_isolated_time`: the most seconds any UAV spent without a single neighbour
+///
+/// The algebraic connectivity of the graph, the second smallest eigenvalue of its Laplacian, is
+/// kept as `mean_algebraic_connectivity` and `min_algebraic_connectivity`. It is 0 while the
+/// graph is disconnected and grows as it gets harder to cut. The seconds each UAV spent isolated
+/// are kept as `isolated_time_ADDRESS`
+pub struct Connectivity {
+    pub time_step: f32,
+    pub radius: f64,
+    pub term: &'static str,
+}
+
+/// The proximity graph at one point in time
+struct Graph {
+    adjacency: Vec<Vec<bool>>,
+}
+
+impl Graph {
+    fn new(positions: &[Vec3], radius: f64) -> Self {
+        let adjacency = positions
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                positions
+                    .iter()
+                    .enumerate()
+                    .map(|(j, b)| i != j && (*b - *a).length() as f64 <= radius)
+                    .collect()
+            })
+            .collect();
+        Self { adjacency }
+    }
+
+    fn degree(&self, node: usize) -> usize {
+        self.adjacency[node]
+            .iter()
+            .filter(|linked| **linked)
+            .count()
+    }
+
+    fn components(&self) -> usize {
+        let mut seen = vec![false; self.adjacency.len()];
+        let mut components = 0;
+        for start in 0..seen.len() {
+            if seen[start] {
+                continue;
+            }
+            components += 1;
+            seen[start] = true;
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for (next, linked) in self.adjacency[node].iter().enumerate() {
+                    if *linked && !seen[next] {
+                        seen[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        components
+    }
+
+    /// The second smallest eigenvalue of the Laplacian. NaN for fewer than two nodes
+    fn algebraic_connectivity(&self) -> f64 {
+        let n = self.adjacency.len();
+        if n < 2 {
+            return f64::NAN;
+        }
+        let laplacian = DMatrix::from_fn(n, n, |i, j| {
+            if i == j {
+                self.degree(i) as f64
+            } else if self.adjacency[i][j] {
+                -1.0
+            } else {
+                0.0
+            }
+        });
+        let mut eigenvalues: Vec<f64> = SymmetricEigen::new(laplacian)
+            .eigenvalues
+            .iter()
+            .copied()
+            .collect();
+        eigenvalues.sort_by(|a, b| a.total_cmp(b));
+        // Rounding leaves disconnected graphs a hair off 0
+        eigenvalues[1].max(0.0)
+    }
+}
+
+impl Fitness for Connectivity {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let samples = sample(data, self.time_step);
+        let uavs: Vec<_> = samples
+            .first()
+            .map(|sample| sample.positions.keys().copied().collect())
+            .unwrap_or_default();
+        let mut isolated_time = vec![0.0; uavs.len()];
+        let mut connectivity = Vec::new();
+        let mut components = Vec::new();
+        let mut connected_samples = 0;
+        for sample in &samples {
+            let positions: Vec<Vec3> = sample.positions.values().copied().collect();
+            let graph = Graph::new(&positions, self.radius);
+            for (node, time) in isolated_time.iter_mut().enumerate() {
+                if graph.degree(node) == 0 {
+                    *time += self.time_step as f64;
+                }
+            }
+            let count = graph.components();
+            if count == 1 {
+                connected_samples += 1;
+            }
+            components.push(count as f64);
+            connectivity.push(graph.algebraic_connectivity());
+        }
+        let connected_fraction = if samples.is_empty() {
+            f64::NAN
+        } else {
+            connected_samples as f64 / samples.len() as f64
+        };
+
+        let mut breakdown = Breakdown::select(
+            self.term,
+            &[
+                ("disconnected_fraction", 1.0 - connected_fraction),
+                ("extra_components", mean(&components) - 1.0),
+                (
+                    "max_isolated_time",
+                    isolated_time.iter().copied().fold(0.0, f64::max),
+                ),
+            ],
+        )
+        .with_metric("connected_fraction", connected_fraction)
+        .with_metric("mean_components", mean(&components))
+        .with_metric("mean_algebraic_connectivity", mean(&connectivity))
+        .with_metric(
+            "min_algebraic_connectivity",
+            connectivity.iter().copied().fold(f64::NAN, f64::min),
+        );
+        for (uav, time) in uavs.iter().zip(isolated_time) {
+            breakdown = breakdown.with_metric(&format!("isolated_time_{}", uav), time);
+        }
+        breakdown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proximity_graph() {
+        // A line of three UAVs 5m apart, the last of which flies off after t=5
+        let mut data = SimulationData::parse(
+            r#"Time (s),IP Address, X (m), Y (m), Z (m)
+0,10.1.1.1,0,0,0,
+0,10.1.1.2,5,0,0,
+0,10.1.1.3,10,0,0,
+5,10.1.1.1,0,0,0,
+5,10.1.1.2,5,0,0,
+5,10.1.1.3,10,0,0,
+6,10.1.1.1,0,0,0,
+6,10.1.1.2,5,0,0,
+6,10.1.1.3,100,0,0,
+10,10.1.1.1,0,0,0,
+10,10.1.1.2,5,0,0,
+10,10.1.1.3,100,0,0,"#,
+        )
+        .unwrap();
+        let connectivity = |term| Connectivity {
+            time_step: 0.5,
+            radius: 6.0,
+            term,
+        };
+        let breakdown = connectivity("disconnected_fraction").evaluate(&mut data);
+        // Linked at t=0..5, out of range from t=5.5 onwards
+        assert!(
+            (breakdown.total - 10.0 / 21.0).abs() < 1e-9,
+            "{}",
+            breakdown.total
+        );
+        assert!((breakdown.metrics["max_isolated_time"] - 5.0).abs() < 1e-9);
+        assert_eq!(breakdown.metrics["isolated_time_10.1.1.1"], 0.0);
+        assert_eq!(breakdown.metrics["isolated_time_10.1.1.3"], 5.0);
+        assert!((breakdown.metrics["extra_components"] - 10.0 / 21.0).abs() < 1e-9);
+        // A path of three nodes has eigenvalues 0, 1 and 3
+        let min = breakdown.metrics["min_algebraic_connectivity"];
+        assert_eq!(min, 0.0);
+        let mean = breakdown.metrics["mean_algebraic_connectivity"];
+        assert!((mean - 11.0 / 21.0).abs() < 1e-9, "{}", mean);
+
+        let everyone = Connectivity {
+            radius: 1000.0,
+            ..connectivity("extra_components")
+        }
+        .evaluate(&mut data);
+        assert_eq!(everyone.total, 0.0);
+        assert_eq!(everyone.metrics["connected_fraction"], 1.0);
+        // A complete graph of n nodes has algebraic connectivity n
+        assert!((everyone.metrics["min_algebraic_connectivity"] - 3.0).abs() < 1e-9);
+    }
+}
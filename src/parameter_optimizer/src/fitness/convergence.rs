@@ -0,0 +1,214 @@
+//! Metrics of how fast the swarm settles into its formation, measured like the step response of
+//! a controller on the central distance and peripheral spacing signals
+
+use super::formation::{central_distance_signal, spacing_signal};
+use super::{central_node, mean, sample, Breakdown, Fitness};
+use crate::position_parser::{SimulationData, UavId};
+
+/// The metrics this module computes. Each one can be the term of a [`Convergence`], which keeps
+/// the others as metrics
+pub const CONVERGENCE_TERMS: [&str; 7] = [
+    "central_rise_time",
+    "central_settling_time",
+    "central_overshoot",
+    "central_steady_state_error",
+    "spacing_rise_time",
+    "spacing_settling_time",
+    "spacing_overshoot",
+];
+
+/// How a signal approached its reference value
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Response {
+    /// Seconds the signal took to go from 10% to 90% of the way from its first value to the
+    /// reference. 0 if it started inside the settling band
+    pub rise_time: f64,
+    /// When the signal entered the settling band for good. The end of the signal if it never did
+    pub settling_time: f64,
+    /// How many meters the signal went past the reference, away from where it started
+    pub overshoot: f64,
+    /// The distance between the mean of the final window and the reference
+    pub steady_state_error: f64,
+    /// The band is this many meters either side of the reference
+    pub band: f64,
+}
+
+impl Response {
+    /// Measures `signal`, given as times and values, against `reference`. The signal has settled
+    /// while it is within `band_fraction` of the reference, and its steady state is its mean over
+    /// the last `final_window` seconds
+    pub fn measure(
+        signal: &[(f32, f64)],
+        reference: f64,
+        band_fraction: f64,
+        final_window: f64,
+    ) -> Self {
+        let band = band_fraction * reference.abs();
+        let (first, last) = match (signal.first(), signal.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => {
+                return Self {
+                    rise_time: f64::NAN,
+                    settling_time: f64::NAN,
+                    overshoot: f64::NAN,
+                    steady_state_error: f64::NAN,
+                    band,
+                }
+            }
+        };
+        let step = reference - first.1;
+
+        let rise_time = if step.abs() <= band {
+            0.0
+        } else {
+            // The first time at least `fraction` of the step is covered
+            let reached = |fraction: f64| {
+                signal
+                    .iter()
+                    .find(|(_, value)| (value - first.1) / step >= fraction)
+                    .map(|(time, _)| *time as f64)
+            };
+            let start = reached(0.1).unwrap_or(first.0 as f64);
+            reached(0.9).unwrap_or(last.0 as f64) - start
+        };
+
+        let settling_time = match signal
+            .iter()
+            .rposition(|(_, value)| (value - reference).abs() > band)
+        {
+            Some(i) => signal.get(i + 1).unwrap_or(&last).0 as f64,
+            None => first.0 as f64,
+        };
+
+        let overshoot = signal
+            .iter()
+            .map(|(_, value)| (value - reference) * step.signum())
+            .fold(0.0, f64::max);
+
+        let final_values: Vec<f64> = signal
+            .iter()
+            .filter(|(time, _)| (last.0 - time) as f64 <= final_window)
+            .map(|(_, value)| *value)
+            .collect();
+        let steady_state_error = (mean(&final_values) - reference).abs();
+
+        Self {
+            rise_time,
+            settling_time,
+            overshoot,
+            steady_state_error,
+            band,
+        }
+    }
+}
+
+/// A signal of the formation and how it settled
+pub struct Signal {
+    /// `central` or `spacing`
+    pub name: &'static str,
+    pub values: Vec<(f32, f64)>,
+    pub reference: f64,
+    pub response: Response,
+}
+
+/// How fast the formation settles. The central distance settles towards `target_distance`. The
+/// peripheral spacing has no target, so it settles towards its own mean over the final window.
+/// The term is one of [`CONVERGENCE_TERMS`]
+pub struct Convergence {
+    pub time_step: f32,
+    pub central_node: Option<UavId>,
+    pub target_distance: f64,
+    /// The fraction of the reference the signals must stay within to have settled
+    pub settling_band: f64,
+    /// Seconds at the end of the simulation that make up the steady state
+    pub final_window: f64,
+    pub term: &'static str,
+}
+
+impl Convergence {
+    /// Measures the central distance and the peripheral spacing signals
+    pub fn signals(&self, data: &mut SimulationData) -> [Signal; 2] {
+        let central = central_node(data, self.central_node);
+        let samples = sample(data, self.time_step);
+        let central_distance = central_distance_signal(&samples, central);
+        let spacing = spacing_signal(&samples, central);
+
+        let end = spacing.last().map_or(0.0, |(time, _)| *time);
+        let final_spacing: Vec<f64> = spacing
+            .iter()
+            .filter(|(time, _)| (end - time) as f64 <= self.final_window)
+            .map(|(_, value)| *value)
+            .collect();
+        let spacing_reference = mean(&final_spacing);
+
+        let signal = |name, values: Vec<(f32, f64)>, reference| Signal {
+            name,
+            response: Response::measure(&values, reference, self.settling_band, self.final_window),
+            values,
+            reference,
+        };
+        [
+            signal("central", central_distance, self.target_distance),
+            signal("spacing", spacing, spacing_reference),
+        ]
+    }
+}
+
+impl Fitness for Convergence {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let [central, spacing] = self.signals(data);
+        let (central, spacing) = (central.response, spacing.response);
+        Breakdown::select(
+            self.term,
+            &[
+                ("central_rise_time", central.rise_time),
+                ("central_settling_time", central.settling_time),
+                ("central_overshoot", central.overshoot),
+                ("central_steady_state_error", central.steady_state_error),
+                ("spacing_rise_time", spacing.rise_time),
+                ("spacing_settling_time", spacing.settling_time),
+                ("spacing_overshoot", spacing.overshoot),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_response() {
+        // Rises from 0 to 10 over 10s, overshoots to 12, then settles on 10 from t=14
+        let signal: Vec<(f32, f64)> = (0..=40)
+            .map(|t| {
+                let value = match t {
+                    0..=10 => t as f64,
+                    11..=12 => 10.0 + (t - 10) as f64,
+                    13 => 10.6,
+                    _ => 10.0,
+                };
+                (t as f32, value)
+            })
+            .collect();
+        let response = Response::measure(&signal, 10.0, 0.05, 10.0);
+        assert_eq!(response.rise_time, 8.0);
+        assert_eq!(response.settling_time, 14.0);
+        assert_eq!(response.overshoot, 2.0);
+        assert_eq!(response.steady_state_error, 0.0);
+        assert_eq!(response.band, 0.5);
+
+        // Falling towards the reference and never settling
+        let falling: Vec<(f32, f64)> = (0..=10).map(|t| (t as f32, 20.0 - t as f64)).collect();
+        let response = Response::measure(&falling, 5.0, 0.05, 2.0);
+        assert_eq!(response.rise_time, 10.0 - 2.0);
+        assert_eq!(response.settling_time, 10.0);
+        assert_eq!(response.overshoot, 0.0);
+        assert_eq!(response.steady_state_error, 6.0);
+
+        let already_there = Response::measure(&[(0.0, 5.1), (1.0, 5.0)], 5.0, 0.05, 1.0);
+        assert_eq!(already_there.rise_time, 0.0);
+        assert_eq!(already_there.settling_time, 0.0);
+        assert!(Response::measure(&[], 5.0, 0.05, 1.0).rise_time.is_nan());
+    }
+}
@@ -0,0 +1,130 @@
+//! Metrics of how well the swarm keeps its formation: peripheral nodes in a ring around the
+//! central node, evenly spaced and holding still
+
+use super::{absolute_deviation, central_node, mean, sample, Breakdown, Fitness, Sample};
+use crate::position_parser::{SimulationData, UavId, Vec3};
+
+use indexmap::IndexMap;
+
+/// Mean absolute deviation over time of the mean distance between peripheral nodes
+pub struct PeripheralSpacing {
+    pub time_step: f32,
+    pub central_node: Option<UavId>,
+}
+
+impl Fitness for PeripheralSpacing {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let central = central_node(data, self.central_node);
+        let samples = sample(data, self.time_step);
+        let spacing: Vec<f64> = spacing_signal(&samples, central)
+            .into_iter()
+            .map(|(_, spacing)| spacing)
+            .collect();
+        Breakdown::single("peripheral_distance_mad", absolute_deviation(&spacing))
+            .with_metric("mean_peripheral_distance", mean(&spacing))
+    }
+}
+
+/// The mean distance between peripheral nodes at each sample, with its time
+pub fn spacing_signal(samples: &[Sample], central: Option<UavId>) -> Vec<(f32, f64)> {
+    samples
+        .iter()
+        .map(|sample| {
+            let peripheral: Vec<_> = sample
+                .positions
+                .iter()
+                .filter(|(uav, _)| Some(**uav) != central)
+                .map(|(_, pos)| *pos)
+                .collect();
+            // Every ordered pair, like the error always used
+            let mut distances = Vec::new();
+            for (i, a) in peripheral.iter().enumerate() {
+                for (j, b) in peripheral.iter().enumerate() {
+                    if i != j {
+                        distances.push((*b - *a).length() as f64);
+                    }
+                }
+            }
+            (sample.time, mean(&distances))
+        })
+        .collect()
+}
+
+/// How far the mean distance of the peripheral nodes to the central node is from the target
+pub struct CentralDistance {
+    pub time_step: f32,
+    pub central_node: Option<UavId>,
+    pub target_distance: f64,
+}
+
+impl Fitness for CentralDistance {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let central = central_node(data, self.central_node);
+        let samples = sample(data, self.time_step);
+        let central_distances: Vec<f64> = central_distance_signal(&samples, central)
+            .into_iter()
+            .map(|(_, distance)| distance)
+            .collect();
+        // A missing central node leaves nothing to measure
+        let mean_central_distance = if central_distances.is_empty() {
+            f64::NAN
+        } else {
+            mean(&central_distances)
+        };
+        Breakdown::single(
+            "central_distance_deviation",
+            (self.target_distance - mean_central_distance).abs(),
+        )
+        .with_metric("mean_central_distance", mean_central_distance)
+    }
+}
+
+/// The mean distance of the peripheral nodes to the central node at each sample, with its time.
+/// Samples without the central node are left out
+pub fn central_distance_signal(samples: &[Sample], central: Option<UavId>) -> Vec<(f32, f64)> {
+    samples
+        .iter()
+        .filter_map(|sample| {
+            let central_pos = *sample.positions.get(&central?)?;
+            let distances: Vec<f64> = sample
+                .positions
+                .iter()
+                .filter(|(uav, _)| Some(**uav) != central)
+                .map(|(_, pos)| (*pos - central_pos).length() as f64)
+                .collect();
+            Some((sample.time, mean(&distances)))
+        })
+        .collect()
+}
+
+/// Mean speed of the nodes over time. The fastest any node went is kept as a metric
+pub struct Velocity {
+    pub time_step: f32,
+}
+
+impl Fitness for Velocity {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let mut last_positions: IndexMap<UavId, (Vec3, f32)> = IndexMap::new();
+        let mut mean_speeds = Vec::new();
+        let mut max_velocity = f64::NAN;
+        for sample in sample(data, self.time_step) {
+            let mut speeds = Vec::new();
+            for (uav, pos) in &sample.positions {
+                if let Some((last_pos, last_time)) = last_positions.get(uav) {
+                    let speed = (*pos - *last_pos).length() / (sample.time - last_time);
+                    speeds.push(speed as f64);
+                }
+                last_positions.insert(*uav, (*pos, sample.time));
+            }
+            // Nothing has moved yet at the first sample
+            mean_speeds.push(mean(&speeds));
+            max_velocity = speeds.iter().copied().fold(max_velocity, f64::max);
+        }
+        let mean_velocity = if mean_speeds.is_empty() {
+            f64::NAN
+        } else {
+            mean(&mean_speeds)
+        };
+        Breakdown::single("mean_velocity", mean_velocity).with_metric("max_velocity", max_velocity)
+    }
+}
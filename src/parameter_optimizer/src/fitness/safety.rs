@@ -0,0 +1,126 @@
+//! Metrics of how close the UAVs come to colliding with each other
+
+use super::{sample, Breakdown, Fitness};
+use crate::position_parser::SimulationData;
+
+/// The metrics this module computes. Each one can be the term of a [`Separation`], which keeps
+/// the others as metrics
+pub const SEPARATION_TERMS: [&str; 3] = [
+    "separation_shortfall",
+    "separation_violations",
+    "separation_violation_time",
+];
+
+/// How close pairs of UAVs get. Two UAVs closer than `threshold` meters are in violation. The
+/// term is one of [`SEPARATION_TERMS`], all of which are lower when the swarm is safer:
+/// - `separation_shortfall`: how far below the threshold the closest pair ever got, or 0
+/// - `separation_violations`: how many times any pair got closer than the threshold
+/// - `separation_violation_time`: seconds during which at least one pair was too close
+///
+/// `min_separation` and `time_to_first_violation` are kept as metrics. The latter is the length
+/// of the simulation when nothing got too close
+pub struct Separation {
+    pub time_step: f32,
+    pub threshold: f64,
+    pub term: &'static str,
+}
+
+impl Fitness for Separation {
+    fn evaluate(&self, data: &mut SimulationData) -> Breakdown {
+        let mut min_separation = f64::INFINITY;
+        let mut violations = 0;
+        let mut violation_time = 0.0;
+        let mut first_violation = None;
+        // Whether each pair was too close at the previous sample, so that a violation lasting
+        // several samples is counted once
+        let mut too_close: Vec<bool> = Vec::new();
+        for sample in sample(data, self.time_step) {
+            let positions: Vec<_> = sample.positions.values().collect();
+            let mut pair = 0;
+            let mut any_too_close = false;
+            for (i, a) in positions.iter().enumerate() {
+                for b in &positions[i + 1..] {
+                    let separation = (**b - **a).length() as f64;
+                    min_separation = min_separation.min(separation);
+                    let close = separation < self.threshold;
+                    if pair == too_close.len() {
+                        too_close.push(false);
+                    }
+                    if close && !too_close[pair] {
+                        violations += 1;
+                    }
+                    too_close[pair] = close;
+                    any_too_close |= close;
+                    pair += 1;
+                }
+            }
+            if any_too_close {
+                violation_time += self.time_step as f64;
+                first_violation.get_or_insert(sample.time as f64);
+            }
+        }
+        if min_separation.is_infinite() {
+            // Fewer than two UAVs, so there was nothing to measure
+            min_separation = f64::NAN;
+        }
+        let time_to_first_violation = first_violation.unwrap_or(data.simulation_length as f64);
+
+        let values = [
+            (
+                "separation_shortfall",
+                (self.threshold - min_separation).max(0.0),
+            ),
+            ("separation_violations", violations as f64),
+            ("separation_violation_time", violation_time),
+        ];
+        Breakdown::select(self.term, &values)
+            .with_metric("min_separation", min_separation)
+            .with_metric("time_to_first_violation", time_to_first_violation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separation() {
+        // Two UAVs fly through each other and are closer than 1m from t=2 to t=3, while a third
+        // stays far away
+        let mut data = SimulationData::parse(
+            r#"Time (s),IP Address, X (m), Y (m), Z (m)
+0,10.1.1.1,0,0,0,
+0,10.1.1.2,5,0,0,
+0,10.1.1.3,0,50,0,
+5,10.1.1.1,5,0,0,
+5,10.1.1.2,0,0,0,
+5,10.1.1.3,0,50,0,"#,
+        )
+        .unwrap();
+        let separation = |term| Separation {
+            time_step: 0.1,
+            threshold: 1.0,
+            term,
+        };
+        let breakdown = separation("separation_violations").evaluate(&mut data);
+        assert_eq!(breakdown.total, 1.0);
+        assert_eq!(breakdown.terms.len(), 1);
+        assert!(breakdown.metrics["min_separation"] < 0.2);
+        let first = breakdown.metrics["time_to_first_violation"];
+        assert!((first - 2.0).abs() < 0.15, "{}", first);
+        let time = breakdown.metrics["separation_violation_time"];
+        assert!((time - 1.0).abs() < 0.15, "{}", time);
+        assert!(!breakdown.metrics.contains_key("separation_violations"));
+
+        let shortfall = separation("separation_shortfall").evaluate(&mut data);
+        assert!(shortfall.total > 0.8);
+
+        let safe = Separation {
+            threshold: 0.0,
+            ..separation("separation_violation_time")
+        }
+        .evaluate(&mut data);
+        assert_eq!(safe.total, 0.0);
+        assert_eq!(safe.metrics["time_to_first_violation"], 5.0);
+    }
+}
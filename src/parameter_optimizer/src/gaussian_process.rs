@@ -0,0 +1,286 @@
+//! Gaussian process regression, which models the error as a smooth function of the parameters and
+//! knows how uncertain it is away from the simulated points.
+//!
+//! Points are in the optimizer's unit hypercube. The values are standardized before fitting, so
+//! the hyperparameters are on the same scale whatever the size of the errors
+
+use nalgebra::{Cholesky, DMatrix, DVector, Dynamic};
+use rand::{Rng, RngCore};
+
+/// Random hyperparameters tried before the best one is refined
+const HYPERPARAMETER_SAMPLES: usize = 40;
+
+/// Halvings of the step of the pattern search that refines the hyperparameters
+const HYPERPARAMETER_REFINEMENTS: usize = 6;
+
+/// Bounds of the natural logarithm of each hyperparameter
+const LOG_LENGTH_SCALE: (f64, f64) = (-4.6, 2.3);
+const LOG_SIGNAL_VARIANCE: (f64, f64) = (-3.0, 3.0);
+const LOG_NOISE_VARIANCE: (f64, f64) = (-13.8, 0.0);
+
+/// The settings of the Matérn 5/2 kernel, with one length scale per dimension
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Hyperparameters {
+    pub length_scales: Vec<f64>,
+    /// Variance of the standardized values explained by the kernel
+    pub signal_variance: f64,
+    /// Variance of the standardized values caused by noise, such as the simulation seed
+    pub noise_variance: f64,
+}
+
+impl Hyperparameters {
+    /// Reasonable settings for when there is too little data to fit them
+    pub fn default_for(dimensions: usize) -> Self {
+        Self {
+            length_scales: vec![0.3; dimensions],
+            signal_variance: 1.0,
+            noise_variance: 1e-4,
+        }
+    }
+
+    fn to_log(&self) -> Vec<f64> {
+        self.length_scales
+            .iter()
+            .chain([self.signal_variance, self.noise_variance].iter())
+            .map(|v| v.ln())
+            .collect()
+    }
+
+    fn from_log(log: &[f64]) -> Self {
+        let dimensions = log.len() - 2;
+        Self {
+            length_scales: log[..dimensions].iter().map(|v| v.exp()).collect(),
+            signal_variance: log[dimensions].exp(),
+            noise_variance: log[dimensions + 1].exp(),
+        }
+    }
+
+    /// Bounds of each value of [`Hyperparameters::to_log`]
+    fn log_bounds(dimensions: usize) -> Vec<(f64, f64)> {
+        let mut bounds = vec![LOG_LENGTH_SCALE; dimensions];
+        bounds.push(LOG_SIGNAL_VARIANCE);
+        bounds.push(LOG_NOISE_VARIANCE);
+        bounds
+    }
+
+    fn kernel(&self, a: &[f64], b: &[f64]) -> f64 {
+        let r = a
+            .iter()
+            .zip(b.iter())
+            .zip(self.length_scales.iter())
+            .map(|((a, b), l)| ((a - b) / l).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        let s = 5f64.sqrt() * r;
+        self.signal_variance * (1.0 + s + s * s / 3.0) * (-s).exp()
+    }
+}
+
+/// A Gaussian process conditioned on a set of observations
+pub struct GaussianProcess {
+    hyperparameters: Hyperparameters,
+    points: Vec<Vec<f64>>,
+    /// Mean and standard deviation used to standardize the values
+    offset: f64,
+    scale: f64,
+    cholesky: Cholesky<f64, Dynamic>,
+    /// The kernel matrix inverse times the standardized values
+    alpha: DVector<f64>,
+    log_likelihood: f64,
+}
+
+impl GaussianProcess {
+    /// Conditions a process with the given hyperparameters on `values` observed at `points`
+    pub fn fit(
+        points: &[Vec<f64>],
+        values: &[f64],
+        hyperparameters: Hyperparameters,
+    ) -> Result<Self, crate::Error> {
+        if points.is_empty() || points.len() != values.len() {
+            return Err("A Gaussian process needs one value for each of its points".into());
+        }
+        let summary = crate::util::summarize(values);
+        let offset = summary.mean;
+        let scale = summary.variance.sqrt().max(1e-12);
+        let y = DVector::from_iterator(values.len(), values.iter().map(|v| (v - offset) / scale));
+
+        let n = points.len();
+        let kernel = DMatrix::from_fn(n, n, |i, j| {
+            let noise = if i == j {
+                hyperparameters.noise_variance
+            } else {
+                0.0
+            };
+            hyperparameters.kernel(&points[i], &points[j]) + noise
+        });
+        let cholesky = kernel
+            .cholesky()
+            .ok_or("Kernel matrix is not positive definite")?;
+        let alpha = cholesky.solve(&y);
+        let log_determinant: f64 = cholesky.l().diagonal().iter().map(|d| d.ln()).sum::<f64>();
+        let log_likelihood = -0.5 * y.dot(&alpha)
+            - log_determinant
+            - 0.5 * n as f64 * (2.0 * std::f64::consts::PI).ln();
+
+        Ok(Self {
+            hyperparameters,
+            points: points.to_vec(),
+            offset,
+            scale,
+            cholesky,
+            alpha,
+            log_likelihood,
+        })
+    }
+
+    /// Picks the hyperparameters that make the observations most likely, starting from `start`
+    /// if given, and conditions the process on the observations with them
+    pub fn fit_hyperparameters(
+        points: &[Vec<f64>],
+        values: &[f64],
+        start: Option<&Hyperparameters>,
+        rng: &mut dyn RngCore,
+    ) -> Result<Self, crate::Error> {
+        let dimensions = points.first().map_or(0, Vec::len);
+        let bounds = Hyperparameters::log_bounds(dimensions);
+        let likelihood = |log: &[f64]| {
+            Self::fit(points, values, Hyperparameters::from_log(log))
+                .map_or(f64::NEG_INFINITY, |gp| gp.log_likelihood)
+        };
+
+        let mut best = start
+            .cloned()
+            .unwrap_or_else(|| Hyperparameters::default_for(dimensions))
+            .to_log();
+        let mut best_likelihood = likelihood(&best);
+        for _ in 0..HYPERPARAMETER_SAMPLES {
+            let candidate: Vec<f64> = bounds
+                .iter()
+                .map(|(low, high)| rng.gen_range(*low..*high))
+                .collect();
+            let candidate_likelihood = likelihood(&candidate);
+            if candidate_likelihood > best_likelihood {
+                best = candidate;
+                best_likelihood = candidate_likelihood;
+            }
+        }
+
+        // Compass search in log space around the best sample
+        let mut step = 0.5;
+        for _ in 0..HYPERPARAMETER_REFINEMENTS {
+            let mut improved = true;
+            while improved {
+                improved = false;
+                for i in 0..best.len() {
+                    for direction in &[step, -step] {
+                        let mut candidate = best.clone();
+                        candidate[i] = (candidate[i] + direction).clamp(bounds[i].0, bounds[i].1);
+                        let candidate_likelihood = likelihood(&candidate);
+                        if candidate_likelihood > best_likelihood + 1e-9 {
+                            best = candidate;
+                            best_likelihood = candidate_likelihood;
+                            improved = true;
+                        }
+                    }
+                }
+            }
+            step /= 2.0;
+        }
+        Self::fit(points, values, Hyperparameters::from_log(&best))
+    }
+
+    pub fn hyperparameters(&self) -> &Hyperparameters {
+        &self.hyperparameters
+    }
+
+    /// Returns the mean and variance of the value at `x`, in the units of the observations
+    pub fn predict(&self, x: &[f64]) -> (f64, f64) {
+        let k = DVector::from_iterator(
+            self.points.len(),
+            self.points
+                .iter()
+                .map(|p| self.hyperparameters.kernel(p, x)),
+        );
+        let mean = k.dot(&self.alpha);
+        let variance =
+            (self.hyperparameters.signal_variance - k.dot(&self.cholesky.solve(&k))).max(0.0);
+        (
+            self.offset + self.scale * mean,
+            self.scale * self.scale * variance,
+        )
+    }
+}
+
+/// The expected amount by which a value with the given mean and variance falls below `best`
+pub fn expected_improvement(mean: f64, variance: f64, best: f64) -> f64 {
+    let stddev = variance.sqrt();
+    if stddev < 1e-12 {
+        return (best - mean).max(0.0);
+    }
+    let z = (best - mean) / stddev;
+    (best - mean) * normal_cdf(z) + stddev * normal_pdf(z)
+}
+
+fn normal_pdf(z: f64) -> f64 {
+    (-0.5 * z * z).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, accurate to about 1.5e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let value = 1.0 - poly * (-x * x).exp();
+    if x < 0.0 {
+        -value
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn fits_smooth_function() {
+        let f = |x: f64| (6.0 * x).sin() + 2.0 * x;
+        let points: Vec<Vec<f64>> = (0..12).map(|i| vec![i as f64 / 11.0]).collect();
+        let values: Vec<f64> = points.iter().map(|p| f(p[0])).collect();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let gp = GaussianProcess::fit_hyperparameters(&points, &values, None, &mut rng).unwrap();
+
+        for x in &[0.05, 0.33, 0.71] {
+            let (mean, _) = gp.predict(&[*x]);
+            assert!(
+                (mean - f(*x)).abs() < 0.05,
+                "f({}) = {} not {}",
+                x,
+                f(*x),
+                mean
+            );
+        }
+        // Far more uncertain outside the observed range than between the points
+        let (_, inside) = gp.predict(&[0.5]);
+        let (_, outside) = gp.predict(&[2.0]);
+        assert!(outside > 100.0 * inside, "{} {}", inside, outside);
+    }
+
+    #[test]
+    fn improvement() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-9);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+        assert_eq!(expected_improvement(3.0, 0.0, 2.0), 0.0);
+        assert_eq!(expected_improvement(1.0, 0.0, 2.0), 1.0);
+        // Uncertainty alone is worth something
+        assert!(expected_improvement(2.0, 1.0, 2.0) > 0.39);
+        assert!(expected_improvement(2.0, 4.0, 2.0) > expected_improvement(2.0, 1.0, 2.0));
+    }
+}
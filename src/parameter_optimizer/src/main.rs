@@ -1,16 +1,32 @@
 use clap::Parser;
 use std::collections::HashMap;
 
+mod adversarial;
+mod constraint;
+mod fidelity;
+mod fitness;
+mod gaussian_process;
 mod git;
 mod optimization;
+mod optimizer;
+mod parameter;
+mod pareto;
 mod position_parser;
+mod robust;
+mod sensitivity;
+mod stopping;
+mod surrogate;
 mod util;
+mod warm_start;
 
 type Error = Box<dyn std::error::Error>;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Name of the person to greet
     #[clap(
         long,
@@ -27,16 +43,338 @@ struct Args {
     #[clap(long, help = "Sets the prefix to use when exporting files")]
     re_export_prefix: Option<String>,
 
+    #[clap(
+        long,
+        help = "Also plots RE_EXPORT_TERM against the parameters when re-exporting. It can be \
+                fitness, an error term such as mean_velocity, a metric such as \
+                mean_central_distance, or what a term added to the error such as \
+                mean_velocity_cost. May be given several times"
+    )]
+    re_export_term: Vec<String>,
+
     /// Number of times to greet
     #[clap(
         long,
         help = "When enabled, checks out the commit that the program wants"
     )]
     use_git: bool,
+
+    #[clap(
+        long,
+        arg_enum,
+        default_value = "tpe",
+        help = "The optimization backend used to pick parameters"
+    )]
+    optimizer: optimizer::OptimizerKind,
+
+    #[clap(
+        long = "param",
+        help = "Adds a parameter to optimize, as NAME=MIN..MAX[,log][,int][,default=VALUE] or \
+                NAME=CHOICE|CHOICE|... for categorical values. Defaults to `a` and `r`"
+    )]
+    params: Vec<parameter::Parameter>,
+
+    #[clap(
+        long,
+        help = "Continues the campaign stored in the json file RESUME, using its optimizer and \
+                parameters"
+    )]
+    resume: Option<String>,
+
+    #[clap(
+        long,
+        default_value = "50",
+        help = "Saves a checkpoint after every CHECKPOINT_EVERY simulations. 0 disables"
+    )]
+    checkpoint_every: usize,
+
+    #[clap(
+        long,
+        default_value = "15",
+        help = "Saves a checkpoint when CHECKPOINT_MINUTES have passed since the last one. 0 disables"
+    )]
+    checkpoint_minutes: f64,
+
+    #[clap(
+        long,
+        default_value = "1",
+        help = "Simulates every point with REPLICATES different seeds and optimizes the mean error"
+    )]
+    replicates: usize,
+
+    #[clap(
+        long,
+        help = "Screens new candidates with MIN_DURATION second simulations and only simulates the \
+                best ones for the full length"
+    )]
+    min_duration: Option<f64>,
+
+    #[clap(
+        long,
+        default_value = "3",
+        help = "How many times longer each screening level is than the previous, and how many \
+                candidates compete for each promotion"
+    )]
+    reduction_factor: usize,
+
+    #[clap(
+        long,
+        help = "Starts some candidates at longer screening levels, like Hyperband's brackets"
+    )]
+    hyperband: bool,
+
+    #[clap(
+        long,
+        default_value = "1000",
+        help = "Stops after MAX_SIMULATIONS full length simulations"
+    )]
+    max_simulations: usize,
+
+    #[clap(long, help = "Stops once the runners have worked for MAX_MINUTES")]
+    max_minutes: Option<f64>,
+
+    #[clap(
+        long,
+        help = "Stops when the best error has not improved in PATIENCE runs"
+    )]
+    patience: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Stops when the best error improved by less than this fraction, such as 0.01, \
+                during the last IMPROVEMENT_WINDOW runs"
+    )]
+    min_improvement: Option<f64>,
+
+    #[clap(
+        long,
+        default_value = "100",
+        help = "How many runs back MIN_IMPROVEMENT is measured from"
+    )]
+    improvement_window: usize,
+
+    #[clap(long, help = "Stops as soon as a run reaches TARGET_ERROR")]
+    target_error: Option<f64>,
+
+    #[clap(
+        long,
+        default_value = "0",
+        help = "Polishes the best REFINE runs with a local pattern search once the search stops"
+    )]
+    refine: usize,
+
+    #[clap(
+        long,
+        default_value = "200",
+        help = "The most runs the refinement may use"
+    )]
+    refine_simulations: usize,
+
+    #[clap(
+        long,
+        help = "Replaces a fixed simulation argument, as NAME=VALUE such as pNodes=16"
+    )]
+    scenario: Vec<warm_start::ScenarioArgument>,
+
+    #[clap(
+        long,
+        help = "Seeds the optimizer with the runs in the output json WARM_START of an earlier \
+                campaign. May be given several times"
+    )]
+    warm_start: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Only warm starts from files whose scenario has NAME=VALUE. May be given several \
+                times"
+    )]
+    warm_start_filter: Vec<warm_start::ScenarioArgument>,
+
+    #[clap(
+        long,
+        default_value = "1",
+        help = "The fraction between 0 and 1 of the earlier runs that are used, picked at random. \
+                Lower values make the optimizer rely less on the earlier campaigns"
+    )]
+    warm_start_fraction: f64,
+
+    #[clap(
+        long,
+        help = "Derives every optimizer and simulation seed from MASTER_SEED. With one worker the \
+                campaign repeats exactly, apart from timestamps. Random when not given"
+    )]
+    master_seed: Option<u64>,
+
+    #[clap(
+        long,
+        help = "How many simulations run in parallel. Defaults to the number of CPUs"
+    )]
+    workers: Option<usize>,
+
+    #[clap(
+        long,
+        arg_enum,
+        help = "Simulates every point in each attack scenario and optimizes the worst-case or CVaR \
+                of their errors"
+    )]
+    robust: Option<robust::RobustMode>,
+
+    #[clap(
+        long,
+        default_value = "0.25",
+        help = "In CVaR mode, the fraction of the worst scenarios whose mean error is optimized"
+    )]
+    cvar_fraction: f64,
+
+    #[clap(
+        long,
+        help = "Adds an attack scenario for robust mode, as NAME or NAME:ARG=VALUE,... such as \
+                jamming:attack=jamming,attackIntensity=0.3. Defaults to clean, takeover, jamming \
+                and spoofing"
+    )]
+    attack_scenario: Vec<robust::AttackScenario>,
+
+    #[clap(
+        long,
+        help = "Searches for the most damaging ADVERSARIAL attack (takeover, jamming or spoofing) \
+                against the swarm parameters fixed with --scenario, such as a=4 and r=7"
+    )]
+    adversarial: Option<String>,
+
+    #[clap(
+        long,
+        help = "Marks runs as infeasible unless NAME<VALUE, NAME<=VALUE, NAME>VALUE or \
+                NAME>=VALUE holds, where NAME is a parameter, fitness, an error term, a metric \
+                or the cost of a term such as mean_velocity_cost. May be given several times"
+    )]
+    constraint: Vec<constraint::Constraint>,
+
+    #[clap(
+        long,
+        help = "Adds a metric to the error, as NAME=WEIGHT such as mean_velocity=250 or \
+                separation_violation_time=100. Replaces the default weighting of \
+                peripheral_distance_mad, central_distance_deviation and mean_velocity. May be \
+                given several times"
+    )]
+    fitness_weight: Vec<fitness::MetricWeight>,
+
+    #[clap(
+        long,
+        help = "Measures RECORD_METRIC in every simulation without adding it to the error, so \
+                that constraints can use it. separation_violations, for one, records \
+                min_separation and time_to_first_violation along with the separation terms. May \
+                be given several times"
+    )]
+    record_metric: Vec<String>,
+
+    #[clap(
+        long,
+        default_value = "7.5",
+        help = "How far from the central node the peripheral nodes should be, in meters"
+    )]
+    target_distance: f64,
+
+    #[clap(
+        long,
+        default_value = "1",
+        help = "UAVs closer than SEPARATION_THRESHOLD meters count as a separation violation"
+    )]
+    separation_threshold: f64,
+
+    #[clap(
+        long,
+        default_value = "0.05",
+        help = "The formation has settled once it stays within this fraction of its target, such \
+                as 0.05 for 5%"
+    )]
+    settling_band: f64,
+
+    #[clap(
+        long,
+        default_value = "30",
+        help = "Seconds at the end of each simulation over which the steady state error is measured"
+    )]
+    final_window: f64,
+
+    #[clap(
+        long,
+        default_value = "10",
+        help = "UAVs within COMMUNICATION_RADIUS meters of each other are linked when measuring \
+                connectivity"
+    )]
+    communication_radius: f64,
+
+    #[clap(
+        long,
+        default_value = "0.1",
+        help = "Seconds between the points in time the trajectories are scored at"
+    )]
+    time_step: f32,
+
+    #[clap(
+        long,
+        help = "The address of the node the others form a ring around. Defaults to the lowest"
+    )]
+    central_node: Option<std::net::IpAddr>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Predicts the error of parameters from the runs of a finished campaign, without simulating
+    Predict {
+        #[clap(help = "The output json of the campaign")]
+        json: String,
+
+        #[clap(
+            required = true,
+            help = "The value of every optimized parameter, as NAME=VALUE such as a=4.2"
+        )]
+        values: Vec<String>,
+    },
+    /// Plots how the formation settled in a positions file, with its rise time, settling time,
+    /// overshoot and steady state error
+    PlotConvergence {
+        #[clap(help = "The positions csv written by the simulation")]
+        positions: String,
+
+        #[clap(default_value = "convergence.png", help = "The image to write")]
+        output: String,
+    },
 }
 
 fn main() {
     let args = Args::parse();
+    let fitness = fitness::FitnessConfig {
+        time_step: args.time_step,
+        central_node: args.central_node,
+        target_distance: args.target_distance,
+        record: args.record_metric.clone(),
+        separation_threshold: args.separation_threshold,
+        settling_band: args.settling_band,
+        final_window: args.final_window,
+        communication_radius: args.communication_radius,
+        ..Default::default()
+    }
+    .with_weights(args.fitness_weight.clone());
+
+    if let Some(Command::Predict { json, values }) = &args.command {
+        let prediction = surrogate::parse_values(values)
+            .and_then(|values| surrogate::predict(json, &values))
+            .expect("Failed to predict");
+        println!(
+            "Predicted error {:.3} ± {:.3} (95% interval {:.3} to {:.3})",
+            prediction.error,
+            prediction.uncertainty,
+            prediction.error - 1.96 * prediction.uncertainty,
+            prediction.error + 1.96 * prediction.uncertainty
+        );
+        return;
+    }
+    if let Some(Command::PlotConvergence { positions, output }) = &args.command {
+        optimization::write_convergence(&fitness, std::path::Path::new(positions), output)
+            .expect("Failed to plot convergence");
+        return;
+    }
 
     let path = "NS3".to_owned();
     if args.use_git {
@@ -65,15 +403,77 @@ fn main() {
 
     if let Some(file_path) = args.re_export {
         println!("Re-exporting data from {}", file_path);
-        optimization::re_export(&file_path, args.re_export_prefix.as_deref())
-            .expect("Failed to re-export data");
+        optimization::re_export(
+            &file_path,
+            args.re_export_prefix.as_deref(),
+            &args.re_export_term,
+        )
+        .expect("Failed to re-export data");
     } else if let Some(dir_path) = args.re_export_all {
-        optimization::re_export_all(&dir_path)
+        optimization::re_export_all(&dir_path, &args.re_export_term)
             .expect("Failed to re-export data");
-        
     } else {
         util::run_waf_command(&path, "build", HashMap::new()).expect("failed to build waf");
 
-        optimization::run(&path);
+        let adversarial = args
+            .adversarial
+            .as_deref()
+            .map(adversarial::Adversarial::new)
+            .transpose()
+            .expect("Bad attack search settings");
+        let robust = match args.robust {
+            Some(mode) => {
+                let aggregation = mode
+                    .aggregation(args.cvar_fraction)
+                    .expect("Bad robust optimization settings");
+                Some(
+                    robust::Robust::new(aggregation, args.attack_scenario)
+                        .expect("Bad robust optimization settings"),
+                )
+            }
+            None => None,
+        };
+
+        optimization::run(
+            &path,
+            optimization::Config {
+                optimizer: args.optimizer,
+                params: args.params,
+                resume: args.resume,
+                checkpoint_every: args.checkpoint_every,
+                checkpoint_minutes: args.checkpoint_minutes,
+                replicates: args.replicates,
+                min_duration: args.min_duration,
+                reduction_factor: args.reduction_factor,
+                hyperband: args.hyperband,
+                stopping: stopping::StoppingRules {
+                    max_simulations: args.max_simulations,
+                    wall_clock: args
+                        .max_minutes
+                        .map(stopping::minutes)
+                        .transpose()
+                        .expect("Bad stopping rules"),
+                    patience: args.patience,
+                    min_improvement: args.min_improvement,
+                    improvement_window: args.improvement_window,
+                    target_error: args.target_error,
+                },
+                refine: args.refine,
+                refine_simulations: args.refine_simulations,
+                scenario: args.scenario,
+                warm_start: Some(warm_start::WarmStart {
+                    files: args.warm_start,
+                    filters: args.warm_start_filter,
+                    fraction: args.warm_start_fraction,
+                })
+                .filter(|warm_start| !warm_start.files.is_empty()),
+                master_seed: args.master_seed,
+                workers: args.workers,
+                robust,
+                adversarial,
+                constraints: args.constraint,
+                fitness,
+            },
+        );
     }
 }
@@ -1,34 +1,26 @@
-use crate::position_parser::{SimulationData, TimePoint};
+use crate::adversarial::{Adversarial, WorstAttack};
+use crate::constraint::Constraint;
+use crate::fidelity::{SuccessiveHalving, Trial};
+use crate::fitness::{Fitness, FitnessConfig};
+use crate::optimizer::{CompassSearch, Optimizer, OptimizerKind};
+use crate::parameter::Parameter;
+use crate::position_parser::SimulationData;
+use crate::robust::{AttackScenario, Robust};
+use crate::sensitivity::{Method, Sensitivity};
+use crate::stopping::{StopReason, StoppingRules};
+use crate::warm_start::{Prior, ScenarioArgument, WarmStart};
 
-use glam::Vec3A;
 use once_cell::sync::OnceCell;
 use plotters::prelude::*;
-use rand::{distributions::Alphanumeric, Rng};
+use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, RngCore, SeedableRng};
 
 use indexmap::IndexMap;
-use std::ops::{Deref, Range};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
-
-#[derive(serde::Serialize, serde::Deserialize)]
-struct Parameter {
-    name: String,
-    #[serde(skip, default = "optim_new")]
-    optim: tpe::TpeOptimizer,
-}
-
-const PARAM_MAX: f64 = 18.0;
-const PARAM_MIN: f64 = 0.0;
-
-fn optim_new() -> tpe::TpeOptimizer {
-    tpe::TpeOptimizer::new(
-        tpe::parzen_estimator(),
-        tpe::range(PARAM_MIN, PARAM_MAX).unwrap(),
-    )
-}
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 
 type State = Arc<Mutex<StateImpl>>;
 
@@ -37,40 +29,1130 @@ struct SimulationRun {
     /// The parameters used in this run
     parameters: IndexMap<String, f64>,
     /// The error score for this run
-    #[serde(rename = "fitness")]
+    #[serde(rename = "fitness", deserialize_with = "crate::util::nan_from_null")]
     error: f64,
     /// The time this run finished
     time: SystemTime,
+    /// The separate terms the error score was computed from
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    objectives: IndexMap<String, f64>,
+    /// What each term added to the error, averaged over the replicates
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    costs: IndexMap<String, f64>,
+    /// The values the terms were computed from, averaged over the replicates
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    metrics: IndexMap<String, f64>,
+    /// The simulations with different seeds that `error` and `objectives` are the mean of
+    #[serde(default)]
+    replicates: Vec<Replicate>,
+    /// Sample variance of the errors of the replicates
+    #[serde(default, deserialize_with = "crate::util::nan_from_null")]
+    error_variance: f64,
+    /// Half width of the 95% confidence interval of `error`
+    #[serde(default, deserialize_with = "crate::util::nan_from_null")]
+    error_confidence: f64,
+    /// How many seconds were simulated. Shorter than `FULL_DURATION` for runs that screened
+    /// candidates in multi-fidelity mode
+    #[serde(default = "full_duration")]
+    duration: f64,
+    /// True for runs from the local refinement phase
+    #[serde(default)]
+    refinement: bool,
+    /// Seed of the random number generator of the runner that asked the optimizer for this
+    /// point. Missing for runs from before campaigns were seeded
+    #[serde(default)]
+    runner_seed: Option<u64>,
+    /// The mean error in each attack scenario, in robust mode. `error` is their aggregate
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    scenario_errors: IndexMap<String, f64>,
+    /// Why the run is infeasible: its simulations kept failing, its error was NaN or it broke a
+    /// constraint. Missing for feasible runs
+    #[serde(default)]
+    infeasible: Option<String>,
+}
+
+impl SimulationRun {
+    /// Infeasible runs are never the best and their error is not used for anything. Files
+    /// written before the reason was recorded mark them with a NaN error
+    fn is_feasible(&self) -> bool {
+        self.infeasible.is_none() && !self.error.is_nan()
+    }
+
+    /// Looks up `fitness`, an error term, a metric or the cost of a term, written as
+    /// `TERM_cost`
+    fn value(&self, name: &str) -> Option<f64> {
+        if name == "fitness" {
+            return Some(self.error);
+        }
+        self.objectives
+            .get(name)
+            .or_else(|| self.metrics.get(name))
+            .or_else(|| {
+                name.strip_suffix("_cost")
+                    .and_then(|term| self.costs.get(term))
+            })
+            .copied()
+    }
+
+    /// The names `value` knows for this run
+    fn value_names(&self) -> Vec<String> {
+        let mut names = vec!["fitness".to_owned()];
+        names.extend(self.objectives.keys().cloned());
+        names.extend(self.metrics.keys().cloned());
+        names.extend(self.costs.keys().map(|term| format!("{}_cost", term)));
+        names
+    }
+}
+
+/// A single simulation of the parameters of a run
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Replicate {
+    /// The seed of the simulation's random number generator
+    seed: u64,
+    #[serde(rename = "fitness", deserialize_with = "crate::util::nan_from_null")]
+    error: f64,
+    #[serde(deserialize_with = "crate::util::nans_from_null")]
+    objectives: IndexMap<String, f64>,
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    costs: IndexMap<String, f64>,
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    metrics: IndexMap<String, f64>,
+    /// The attack scenario that was simulated, in robust mode
+    #[serde(default)]
+    scenario: Option<String>,
+}
+
+/// A point suggested by the optimizer whose replicates are spread over the runners
+struct PendingPoint {
+    id: usize,
+    /// The point in the optimizer's unit hypercube
+    point: Vec<f64>,
+    parameters: IndexMap<String, f64>,
+    /// The rung this point is simulated in when running in multi-fidelity mode
+    trial: Option<Trial>,
+    duration: f64,
+    /// The scenario index of each replicate that no runner has started yet. Always 0 outside of
+    /// robust mode
+    unstarted: Vec<usize>,
+    failures: usize,
+    finished: Vec<Replicate>,
+    runner_seed: u64,
+}
+
+/// A simulation handed to a runner
+struct Evaluation {
+    /// Id of the pending point this is a replicate of
+    id: usize,
+    parameters: IndexMap<String, f64>,
+    duration: f64,
+    /// Index of the attack scenario. Always 0 outside of robust mode
+    scenario: usize,
+    /// The attack scenario to simulate, in robust mode
+    attack: Option<AttackScenario>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct StateImpl {
+    /// The optimization backend that suggested the parameters for each run
+    #[serde(default)]
+    optimizer: OptimizerKind,
+
+    /// The state of the optimization backend at the time this file was written
+    #[serde(default)]
+    optimizer_state: serde_json::Value,
+
     /// The parameters in use. The `name` value in the Parameter struct corresponds with the
     /// key in a `SimulationRun`'s `parameters` map
     params: Vec<Parameter>,
 
+    /// The fixed simulation arguments, by name. Parameters override them
+    #[serde(default = "default_scenario")]
+    scenario: IndexMap<String, String>,
+
+    /// Runs of earlier campaigns that were told to the optimizer before the first simulation
+    #[serde(default)]
+    priors: Vec<Prior>,
+
+    /// Every random number generator of the campaign is seeded from this. Missing in files
+    /// written before campaigns were seeded
+    #[serde(default)]
+    master_seed: Option<u64>,
+
     /// Finished runs
     results: Vec<SimulationRun>,
+
+    /// Indices into `results` of the runs that no other run beats in every objective
+    #[serde(default)]
+    pareto_front: Vec<usize>,
+
+    /// How many seeds each point is simulated with
+    #[serde(default = "default_replicates")]
+    replicates: usize,
+
+    /// Schedules short screening simulations before full length ones, if enabled
+    #[serde(default)]
+    fidelity: Option<SuccessiveHalving>,
+
+    /// Simulates every point in several attack scenarios and scores it by how it holds up in
+    /// the worst of them, if enabled
+    #[serde(default)]
+    robust: Option<Robust>,
+
+    /// Searches for the most damaging attack instead of the best swarm parameters, if enabled.
+    /// The optimizer minimizes, so the fitness of every run is the negated formation error
+    #[serde(default)]
+    adversarial: Option<Adversarial>,
+
+    /// Runs that break any of these are infeasible
+    #[serde(default)]
+    constraints: Vec<Constraint>,
+
+    /// How the error of each simulation is computed
+    #[serde(default)]
+    fitness: FitnessConfig,
+
+    /// Why the runners stopped. Missing while they are still working
+    #[serde(default)]
+    stop_reason: Option<StopReason>,
+
+    #[serde(skip)]
+    stopping: StoppingRules,
+
+    /// Points which still have replicates waiting or running
+    #[serde(skip)]
+    pending: Vec<PendingPoint>,
+
+    #[serde(skip)]
+    next_pending_id: usize,
+
+    /// The live optimizer. Only present while the runners are working
+    #[serde(skip)]
+    backend: Option<Box<dyn Optimizer>>,
+
+    /// Replaces `backend` during the local refinement phase
+    #[serde(skip)]
+    refinement: Option<Refinement>,
+
+    /// Where and how often to save checkpoints while the runners are working
+    #[serde(skip)]
+    checkpoint: Option<Checkpointer>,
+}
+
+/// What the Gaussian process predicts over a grid of two parameters
+#[derive(serde::Serialize)]
+struct ModelSurface {
+    x_param: String,
+    y_param: String,
+    /// The values of the other parameters
+    fixed: IndexMap<String, f64>,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    /// Predicted error, indexed by the position along `y` and then along `x`
+    mean: Vec<Vec<f64>>,
+    variance: Vec<Vec<f64>>,
+}
+
+/// Cells along each axis of the exported model surface
+const SURFACE_STEPS: usize = 50;
+
+/// A local search that polishes the best results once the global search has stopped
+struct Refinement {
+    backend: Box<dyn Optimizer>,
+    /// Stop after this many refinement runs
+    max_runs: usize,
+    /// Index of the first result of the refinement phase
+    first_run: usize,
+}
+
+/// Decides when the state should be written to disk so that a crash doesn't lose finished runs.
+/// Checks happen whenever a simulation finishes, so the time based interval is approximate
+struct Checkpointer {
+    path: PathBuf,
+    /// Save after this many new simulations
+    every: Option<usize>,
+    /// Save when this much time has passed since the last checkpoint
+    interval: Option<Duration>,
+    last_simulations: usize,
+    last_time: Instant,
+}
+
+impl Checkpointer {
+    fn is_due(&self, simulations: usize) -> bool {
+        let count_due =
+            matches!(self.every, Some(every) if simulations >= self.last_simulations + every);
+        let time_due =
+            matches!(self.interval, Some(interval) if self.last_time.elapsed() >= interval);
+        count_due || time_due
+    }
+}
+
+// Serde needs functions for default values
+fn default_replicates() -> usize {
+    1
+}
+
+fn full_duration() -> f64 {
+    FULL_DURATION
+}
+
+/// Streams of the master seed used for the random number generators of a campaign. Runners derive
+/// theirs from `RUNNER_STREAM`
+const DESIGN_STREAM: u64 = 0;
+const WARM_START_STREAM: u64 = 1;
+const RUNNER_STREAM: u64 = 2;
+
+/// The scenario every campaign used before it was recorded
+fn default_scenario() -> IndexMap<String, String> {
+    BASE_ARGUMENTS
+        .iter()
+        .map(|arg| {
+            let argument: ScenarioArgument = arg.parse().unwrap();
+            (argument.name, argument.value)
+        })
+        .collect()
+}
+
+/// The mean of each value over `maps`, by name
+fn mean_by_name<'a>(
+    maps: impl ExactSizeIterator<Item = &'a IndexMap<String, f64>>,
+) -> IndexMap<String, f64> {
+    let count = maps.len() as f64;
+    let mut means: IndexMap<String, f64> = IndexMap::new();
+    for map in maps {
+        for (name, value) in map {
+            *means.entry(name.clone()).or_default() += value / count;
+        }
+    }
+    means
+}
+
+/// Maps the parameters of a run into the optimizer's unit hypercube. Returns `None` if the run
+/// is missing one of `params`
+fn normalized_point(params: &[Parameter], parameters: &IndexMap<String, f64>) -> Option<Vec<f64>> {
+    params
+        .iter()
+        .map(|p| parameters.get(&p.name).map(|v| p.normalize(*v)))
+        .collect()
+}
+
+/// Tells the optimizer about the runs of earlier campaigns
+fn tell_priors(
+    backend: &mut dyn Optimizer,
+    params: &[Parameter],
+    priors: &[Prior],
+) -> Result<(), crate::Error> {
+    for prior in priors {
+        if let Some(point) = normalized_point(params, &prior.parameters) {
+            let objectives: Vec<f64> = prior.objectives.values().copied().collect();
+            backend.tell_objectives(&point, prior.error, &objectives)?;
+        }
+    }
+    Ok(())
+}
+
+/// How often a replicate may fail before its point is given up on
+const MAX_REPLICATE_FAILURES: usize = 3;
+
+/// How many points in a row may break the constraints on the parameters before the optimizer is
+/// assumed to be stuck in an infeasible region
+const MAX_REJECTED_POINTS: usize = 1000;
+
+impl StateImpl {
+    /// Returns the declaration of the parameter called `name`
+    fn param(&self, name: &str) -> Parameter {
+        self.params
+            .iter()
+            .find(|p| p.name == name)
+            .cloned()
+            .unwrap_or_else(|| Parameter::legacy(name))
+    }
+
+    /// Returns the indices of the runs on the Pareto front of the objectives. Runs without
+    /// objectives or with NaN values are ignored
+    fn compute_pareto_front(&self) -> Vec<usize> {
+        let candidates: Vec<usize> = (0..self.results.len())
+            .filter(|i| {
+                let objectives = &self.results[*i].objectives;
+                self.results[*i].is_feasible()
+                    && !objectives.is_empty()
+                    && objectives.values().all(|v| !v.is_nan())
+            })
+            .collect();
+        let points: Vec<Vec<f64>> = candidates
+            .iter()
+            .map(|i| self.results[*i].objectives.values().copied().collect())
+            .collect();
+        crate::pareto::pareto_front(&points)
+            .into_iter()
+            .map(|i| candidates[i])
+            .collect()
+    }
+
+    /// Returns how many full length simulations the finished runs are worth
+    fn full_simulations(&self) -> f64 {
+        self.results
+            .iter()
+            .map(|r| r.duration / FULL_DURATION)
+            .sum()
+    }
+
+    /// Returns the runs with the longest duration, which are the only ones with comparable errors
+    fn longest_runs(&self) -> Vec<&SimulationRun> {
+        let longest = self.results.iter().map(|r| r.duration).fold(0.0, f64::max);
+        self.results
+            .iter()
+            .filter(|r| r.duration == longest)
+            .collect()
+    }
+
+    fn is_sensitivity_analysis(&self) -> bool {
+        matches!(self.optimizer, OptimizerKind::Sobol | OptimizerKind::Morris)
+    }
+
+    /// Evaluates the Gaussian process of the `gp` optimizer on a `steps` by `steps` grid over two
+    /// parameters. The other parameters are held at their values in the best run. Returns `None`
+    /// for other optimizers or when there is no model yet
+    fn model_surface(
+        &self,
+        x_param: &str,
+        y_param: &str,
+        x_range: Range<f64>,
+        y_range: Range<f64>,
+        steps: usize,
+    ) -> Result<Option<ModelSurface>, crate::Error> {
+        if self.optimizer != OptimizerKind::Gp {
+            return Ok(None);
+        }
+        let model = match crate::optimizer::saved_model(&self.optimizer_state)? {
+            Some(model) => model,
+            None => return Ok(None),
+        };
+        let best = self
+            .longest_runs()
+            .into_iter()
+            .filter(|r| r.is_feasible())
+            .min_by(|a, b| a.error.partial_cmp(&b.error).unwrap());
+        let fixed: IndexMap<String, f64> = self
+            .params
+            .iter()
+            .filter(|p| p.name != x_param && p.name != y_param)
+            .map(|p| {
+                let value = best
+                    .and_then(|r| r.parameters.get(&p.name).copied())
+                    .unwrap_or_else(|| p.denormalize(p.initial()));
+                (p.name.clone(), value)
+            })
+            .collect();
+
+        let axis = |range: &Range<f64>| -> Vec<f64> {
+            (0..steps)
+                .map(|i| range.start + (range.end - range.start) * i as f64 / (steps - 1) as f64)
+                .collect()
+        };
+        let (x, y) = (axis(&x_range), axis(&y_range));
+        let mut mean = Vec::with_capacity(steps);
+        let mut variance = Vec::with_capacity(steps);
+        for y_value in &y {
+            let (row_mean, row_variance): (Vec<f64>, Vec<f64>) = x
+                .iter()
+                .map(|x_value| {
+                    let point: Vec<f64> = self
+                        .params
+                        .iter()
+                        .map(|p| {
+                            let value = match p.name.as_str() {
+                                name if name == x_param => *x_value,
+                                name if name == y_param => *y_value,
+                                name => fixed[name],
+                            };
+                            p.normalize(value)
+                        })
+                        .collect();
+                    model.predict(&point)
+                })
+                .unzip();
+            mean.push(row_mean);
+            variance.push(row_variance);
+        }
+        Ok(Some(ModelSurface {
+            x_param: x_param.to_owned(),
+            y_param: y_param.to_owned(),
+            fixed,
+            x,
+            y,
+            mean,
+            variance,
+        }))
+    }
+
+    /// Checks the stopping rules against the full length runs, recording the first reason to
+    /// stop that is found
+    fn check_stopping(&mut self) -> Option<StopReason> {
+        let errors: Vec<f64> = self
+            .results
+            .iter()
+            .filter(|r| r.duration == FULL_DURATION)
+            .map(|r| if r.is_feasible() { r.error } else { f64::NAN })
+            .collect();
+        let elapsed = STARTED.get().map_or(Duration::ZERO, Instant::elapsed);
+        let reason = if let Some(refinement) = &self.refinement {
+            // The global rules already stopped the search once, so only the refinement's own
+            // budget applies
+            if refinement.backend.is_finished() {
+                Some(StopReason::Converged)
+            } else if self.results.len() >= refinement.first_run + refinement.max_runs {
+                Some(StopReason::MaxSimulations)
+            } else {
+                None
+            }
+        } else if matches!(&self.backend, Some(backend) if backend.is_finished()) {
+            Some(StopReason::DesignComplete)
+        } else {
+            self.stopping
+                .check(&errors, self.full_simulations(), elapsed)
+        };
+        if self.stop_reason.is_none() {
+            self.stop_reason = reason;
+        }
+        reason
+    }
+
+    /// Hands out the next simulation to run: a replicate of a pending point if one still needs a
+    /// runner, otherwise the first replicate of a new point
+    fn next_evaluation(
+        &mut self,
+        rng: &mut dyn RngCore,
+        runner_seed: u64,
+    ) -> Result<Evaluation, crate::Error> {
+        if let Some(pending) = self.pending.iter_mut().find(|p| !p.unstarted.is_empty()) {
+            let scenario = pending.unstarted.pop().unwrap();
+            let (id, parameters, duration) =
+                (pending.id, pending.parameters.clone(), pending.duration);
+            return Ok(self.evaluation(id, parameters, duration, scenario));
+        }
+
+        let in_flight: Vec<Vec<f64>> = self.pending.iter().map(|p| p.point.clone()).collect();
+        let mut rejected = 0;
+        let (point, trial, parameters) = loop {
+            let backend = self.backend.as_mut().unwrap();
+            let (point, trial) = match &mut self.fidelity {
+                // Refinement always simulates the full length
+                _ if self.refinement.is_some() => {
+                    (self.refinement.as_mut().unwrap().backend.ask(rng)?, None)
+                }
+                Some(fidelity) => {
+                    let trial = fidelity.next(|| backend.ask_pending(rng, &in_flight))?;
+                    (trial.point.clone(), Some(trial))
+                }
+                None => (backend.ask_pending(rng, &in_flight)?, None),
+            };
+            let parameters: IndexMap<String, f64> = self
+                .params
+                .iter()
+                .zip(point.iter())
+                .map(|(param, unit_value)| (param.name.clone(), param.denormalize(*unit_value)))
+                .collect();
+            // Points that break a constraint on the parameters are never simulated
+            let violation = self
+                .constraints
+                .iter()
+                .filter(|c| self.params.iter().any(|p| p.name == c.name))
+                .find_map(|c| c.violation(&parameters));
+            let reason = match violation {
+                Some(reason) => reason,
+                None => break (point, trial, parameters),
+            };
+            if let (Some(fidelity), Some(trial)) = (&mut self.fidelity, &trial) {
+                fidelity.record(trial, f64::NAN);
+            }
+            self.record_infeasible(&point, parameters, 0.0, Vec::new(), reason, runner_seed)?;
+            rejected += 1;
+            if rejected == MAX_REJECTED_POINTS {
+                return Err(format!(
+                    "The optimizer suggested {} points in a row that break the constraints",
+                    rejected
+                )
+                .into());
+            }
+        };
+        let duration = trial.as_ref().map_or(FULL_DURATION, |t| t.duration);
+        let id = self.next_pending_id;
+        self.next_pending_id += 1;
+        // Every scenario gets a replicate before any gets a second one. They are popped from the
+        // back
+        let mut unstarted: Vec<usize> = (0..self.replicates)
+            .flat_map(|_| (0..self.scenario_count()).rev())
+            .collect();
+        let scenario = unstarted.pop().unwrap();
+        self.pending.push(PendingPoint {
+            id,
+            point,
+            parameters: parameters.clone(),
+            trial,
+            duration,
+            unstarted,
+            failures: 0,
+            finished: Vec::new(),
+            runner_seed,
+        });
+        Ok(self.evaluation(id, parameters, duration, scenario))
+    }
+
+    /// How many scenarios each point is simulated in
+    fn scenario_count(&self) -> usize {
+        self.robust
+            .as_ref()
+            .map_or(1, |robust| robust.scenarios.len())
+    }
+
+    fn evaluation(
+        &self,
+        id: usize,
+        parameters: IndexMap<String, f64>,
+        duration: f64,
+        scenario: usize,
+    ) -> Evaluation {
+        Evaluation {
+            id,
+            parameters,
+            duration,
+            scenario,
+            attack: self
+                .robust
+                .as_ref()
+                .map(|robust| robust.scenarios[scenario].clone()),
+        }
+    }
+
+    /// Gives the replicate of a failed simulation back so that another runner retries it. Once
+    /// the point fails too often it is recorded as infeasible, with `reason` for the last failure
+    fn abandon_replicate(
+        &mut self,
+        evaluation: &Evaluation,
+        reason: &str,
+    ) -> Result<(), crate::Error> {
+        if let Some(index) = self.pending.iter().position(|p| p.id == evaluation.id) {
+            let pending = &mut self.pending[index];
+            pending.failures += 1;
+            if pending.failures >= MAX_REPLICATE_FAILURES {
+                println!(
+                    "Dropping {:?} after {} failed simulations",
+                    pending.parameters, pending.failures
+                );
+                let pending = self.pending.remove(index);
+                if let (Some(fidelity), Some(trial)) = (&mut self.fidelity, &pending.trial) {
+                    fidelity.record(trial, f64::NAN);
+                }
+                self.record_infeasible(
+                    &pending.point,
+                    pending.parameters,
+                    pending.duration,
+                    pending.finished,
+                    format!("simulation failed {} times: {}", pending.failures, reason),
+                    pending.runner_seed,
+                )?;
+            } else {
+                pending.unstarted.push(evaluation.scenario);
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a point that could not be scored and tells the optimizer to avoid it
+    fn record_infeasible(
+        &mut self,
+        point: &[f64],
+        parameters: IndexMap<String, f64>,
+        duration: f64,
+        replicates: Vec<Replicate>,
+        reason: String,
+        runner_seed: u64,
+    ) -> Result<(), crate::Error> {
+        println!("  infeasible: {}", reason);
+        self.tell_backend(point, f64::NAN, &[], false, true)?;
+        self.results.push(SimulationRun {
+            parameters,
+            error: f64::NAN,
+            time: SystemTime::now(),
+            objectives: IndexMap::new(),
+            costs: IndexMap::new(),
+            metrics: IndexMap::new(),
+            replicates,
+            error_variance: 0.0,
+            error_confidence: 0.0,
+            duration,
+            refinement: self.refinement.is_some(),
+            runner_seed: Some(runner_seed),
+            scenario_errors: IndexMap::new(),
+            infeasible: Some(reason),
+        });
+        Ok(())
+    }
+
+    /// Tells the optimizer in charge how a point did. Infeasible points are told with a penalty,
+    /// whatever their duration, since a point that can't be simulated briefly can't be simulated
+    /// at full length either
+    fn tell_backend(
+        &mut self,
+        point: &[f64],
+        error: f64,
+        objectives: &[f64],
+        full_length: bool,
+        infeasible: bool,
+    ) -> Result<(), crate::Error> {
+        let penalty = self.penalty();
+        let backend = match &mut self.refinement {
+            Some(refinement) => &mut refinement.backend,
+            None if full_length || infeasible => self.backend.as_mut().unwrap(),
+            None => return Ok(()),
+        };
+        if infeasible {
+            backend.tell_infeasible(point, penalty)
+        } else {
+            backend.tell_objectives(point, error, objectives)
+        }
+    }
+
+    /// The error told to the optimizer about infeasible points
+    fn penalty(&self) -> f64 {
+        crate::optimizer::infeasible_penalty(
+            self.results
+                .iter()
+                .filter(|r| r.is_feasible() && r.duration == FULL_DURATION)
+                .map(|r| r.error)
+                .chain(self.priors.iter().map(|p| p.error)),
+        )
+    }
+
+    /// Records a finished replicate. Once every replicate of its point is in, the new run is
+    /// returned and the optimizer is told the mean error. In multi-fidelity mode the optimizer
+    /// only learns from full length simulations, because shorter ones have different errors
+    fn finish_replicate(
+        &mut self,
+        id: usize,
+        mut replicate: Replicate,
+    ) -> Result<Option<&SimulationRun>, crate::Error> {
+        if self.adversarial.is_some() {
+            replicate.error = -replicate.error;
+            // Costs add up to the error, the metrics stay as they were measured
+            for value in replicate
+                .objectives
+                .values_mut()
+                .chain(replicate.costs.values_mut())
+            {
+                *value = -*value;
+            }
+        }
+        // The point may have been dropped while this replicate was running
+        let index = match self.pending.iter().position(|p| p.id == id) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        self.pending[index].finished.push(replicate);
+        if self.pending[index].finished.len() < self.replicates * self.scenario_count() {
+            return Ok(None);
+        }
+
+        let pending = self.pending.remove(index);
+        let errors: Vec<f64> = pending.finished.iter().map(|r| r.error).collect();
+        let mut summary = crate::util::summarize(&errors);
+        let mut objectives = mean_by_name(pending.finished.iter().map(|r| &r.objectives));
+        let costs = mean_by_name(pending.finished.iter().map(|r| &r.costs));
+        let metrics = mean_by_name(pending.finished.iter().map(|r| &r.metrics));
+        let mut scenario_errors = IndexMap::new();
+        if let Some(robust) = &self.robust {
+            fn scenario_of(replicate: &Replicate) -> &str {
+                replicate.scenario.as_deref().unwrap_or_default()
+            }
+            let (means, score) =
+                robust.score(pending.finished.iter().map(|r| (scenario_of(r), r.error)));
+            // The spread of the score is that of the scenario it was decided by
+            let worst = means
+                .iter()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default();
+            let worst_errors: Vec<f64> = pending
+                .finished
+                .iter()
+                .filter(|r| scenario_of(r) == worst)
+                .map(|r| r.error)
+                .collect();
+            summary = crate::util::summarize(&worst_errors);
+            summary.mean = score;
+            // Each term is made robust on its own, as if it were the only objective
+            for (name, value) in objectives.iter_mut() {
+                *value = robust
+                    .score(
+                        pending
+                            .finished
+                            .iter()
+                            .map(|r| (scenario_of(r), r.objectives[name])),
+                    )
+                    .1;
+            }
+            scenario_errors = means;
+        }
+
+        let infeasible = if summary.mean.is_nan() {
+            Some("the error is NaN".to_owned())
+        } else {
+            let mut values = pending.parameters.clone();
+            values.extend(
+                objectives
+                    .iter()
+                    .chain(&metrics)
+                    .map(|(name, value)| (name.clone(), *value)),
+            );
+            values.extend(
+                costs
+                    .iter()
+                    .map(|(name, value)| (format!("{}_cost", name), *value)),
+            );
+            values.insert("fitness".to_owned(), summary.mean);
+            self.constraints.iter().find_map(|c| c.violation(&values))
+        };
+        if let Some(reason) = &infeasible {
+            println!("  infeasible: {}", reason);
+        }
+
+        let full_length = match (&mut self.fidelity, &pending.trial) {
+            (Some(fidelity), Some(trial)) => {
+                // Infeasible points are never promoted
+                let error = if infeasible.is_some() {
+                    f64::NAN
+                } else {
+                    summary.mean
+                };
+                fidelity.record(trial, error);
+                fidelity.is_full(trial.rung)
+            }
+            _ => true,
+        };
+        let values: Vec<f64> = objectives.values().copied().collect();
+        self.tell_backend(
+            &pending.point,
+            summary.mean,
+            &values,
+            full_length,
+            infeasible.is_some(),
+        )?;
+        self.results.push(SimulationRun {
+            parameters: pending.parameters,
+            error: summary.mean,
+            time: SystemTime::now(),
+            objectives,
+            costs,
+            metrics,
+            replicates: pending.finished,
+            error_variance: summary.variance,
+            error_confidence: summary.confidence,
+            duration: pending.duration,
+            refinement: self.refinement.is_some(),
+            runner_seed: Some(pending.runner_seed),
+            scenario_errors,
+            infeasible,
+        });
+        Ok(self.results.last())
+    }
+
+    /// Switches to a compass search around the `starts` best full length runs, which stops after
+    /// `max_runs` runs or once it converges
+    fn start_refinement(&mut self, starts: usize, max_runs: usize) -> Result<(), crate::Error> {
+        let mut candidates: Vec<&SimulationRun> = self
+            .results
+            .iter()
+            .filter(|r| r.duration == FULL_DURATION && r.is_feasible())
+            .collect();
+        candidates.sort_by(|a, b| a.error.partial_cmp(&b.error).unwrap());
+
+        let mut points: Vec<(Vec<f64>, f64)> = Vec::new();
+        for run in candidates {
+            match normalized_point(&self.params, &run.parameters) {
+                Some(point) if !points.iter().any(|(p, _)| *p == point) => {
+                    points.push((point, run.error))
+                }
+                _ => {}
+            }
+            if points.len() == starts {
+                break;
+            }
+        }
+        if points.is_empty() {
+            return Err("No finished runs to refine".into());
+        }
+
+        println!("Refining the best {} runs", points.len());
+        self.refinement = Some(Refinement {
+            backend: Box::new(CompassSearch::new(points)),
+            max_runs,
+            first_run: self.results.len(),
+        });
+        // Partially replicated points of the global search will never be finished
+        self.pending.clear();
+        self.stop_reason = None;
+        Ok(())
+    }
+
+    /// Writes this state to `path` in the same format that `re_export` and `--resume` read.
+    /// The data is written to a temporary file first and then renamed, so `path` never contains
+    /// a partially written file
+    fn write_json(&mut self, path: &Path) -> Result<(), crate::Error> {
+        if let Some(backend) = &self.backend {
+            self.optimizer_state = backend.save_state();
+        }
+        self.pareto_front = self.compute_pareto_front();
+        let json = serde_json::to_string(self)?;
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Saves a checkpoint if enough simulations or time have passed since the last one
+    fn checkpoint_if_due(&mut self) {
+        let simulations = self.results.len();
+        let path = match &self.checkpoint {
+            Some(checkpoint) if checkpoint.is_due(simulations) => checkpoint.path.clone(),
+            _ => return,
+        };
+        match self.write_json(&path) {
+            Ok(()) => println!("  checkpoint saved to {}", path.to_str().unwrap()),
+            Err(err) => println!("Failed to save checkpoint: {}", err),
+        }
+        let checkpoint = self.checkpoint.as_mut().unwrap();
+        checkpoint.last_simulations = simulations;
+        checkpoint.last_time = Instant::now();
+    }
 }
 
 static RUNNING: AtomicBool = AtomicBool::new(true);
 static PATH: OnceCell<String> = OnceCell::new();
 static STATE: OnceCell<State> = OnceCell::new();
+/// When the runners were started, for the wall clock budget
+static STARTED: OnceCell<Instant> = OnceCell::new();
 
-static BASE_ARGUMENTS: [&str; 5] = [
-    "--duration=180",
+static BASE_ARGUMENTS: [&str; 4] = [
     "--pNodes=8",
     "--packetInterval=0.3",
     "--calculateInterval=0.01",
     "--spawnRadius=8.5",
 ];
-const TARGET_DISTANCE: f64 = 7.5;
 
-const MAX_SIMULATIONS: usize = 1000;
+/// How many seconds a full length simulation lasts
+const FULL_DURATION: f64 = 180.0;
 
 static LOWEST_ERROR: atomic_float::AtomicF64 = atomic_float::AtomicF64::new(10000.0);
 
-pub fn run(path: &str) {
+/// The parameters that are optimized when none are given on the command line: the attraction
+/// and repulsion constants of the swarm
+fn default_parameters() -> Vec<Parameter> {
+    vec![
+        Parameter::float("a", 0.0, 18.0).with_default(1.0),
+        Parameter::float("r", 0.0, 18.0).with_default(1.0),
+    ]
+}
+
+/// Settings for an optimization campaign
+pub struct Config {
+    pub optimizer: OptimizerKind,
+    /// The parameters to optimize. When empty `a` and `r` are used
+    pub params: Vec<Parameter>,
+    /// The output json of an earlier campaign to continue. Its optimizer, parameters, scenario
+    /// and warm start runs take precedence over the rest of this config
+    pub resume: Option<String>,
+    /// Save a checkpoint after this many simulations. Zero disables count based checkpoints
+    pub checkpoint_every: usize,
+    /// Save a checkpoint after this many minutes. Zero disables time based checkpoints
+    pub checkpoint_minutes: f64,
+    /// How many seeds to simulate each point with. The optimizer is told the mean error
+    pub replicates: usize,
+    /// Enables multi-fidelity mode, screening new candidates with simulations this many seconds
+    /// long
+    pub min_duration: Option<f64>,
+    /// How many times longer each fidelity level is than the one below, and how many candidates
+    /// are screened for every one promoted
+    pub reduction_factor: usize,
+    /// Starts some candidates at higher fidelity levels like Hyperband does, instead of always
+    /// starting at the shortest duration
+    pub hyperband: bool,
+    /// When to stop the runners. These apply to resumed campaigns as well
+    pub stopping: StoppingRules,
+    /// How many of the best runs to polish with a local search once the global search stops.
+    /// Zero disables the refinement phase
+    pub refine: usize,
+    /// The most runs the refinement phase may use
+    pub refine_simulations: usize,
+    /// Replaces or adds to the fixed simulation arguments
+    pub scenario: Vec<ScenarioArgument>,
+    /// Earlier campaigns to seed the optimizer with
+    pub warm_start: Option<WarmStart>,
+    /// Seeds every random number generator, so that a campaign with a single worker can be
+    /// repeated exactly. A random seed is picked when missing
+    pub master_seed: Option<u64>,
+    /// How many simulations run in parallel. Defaults to the number of CPUs
+    pub workers: Option<usize>,
+    /// Scores every point across attack scenarios instead of in a single clean simulation
+    pub robust: Option<Robust>,
+    /// Searches for the attack that does the most damage to the swarm in the scenario. When no
+    /// parameters are given the attack's own are searched
+    pub adversarial: Option<Adversarial>,
+    /// Runs that break any of these are recorded as infeasible
+    pub constraints: Vec<Constraint>,
+    /// How the error of each simulation is computed
+    pub fitness: FitnessConfig,
+}
+
+/// Creates the state for a campaign that starts from scratch
+fn new_state(config: Config, workers: usize) -> Result<StateImpl, crate::Error> {
+    let mut scenario = default_scenario();
+    for argument in config.scenario {
+        scenario.insert(argument.name, argument.value);
+    }
+    if let Some(adversarial) = &config.adversarial {
+        if config.robust.is_some() {
+            return Err("Robust mode already picks the attacks, it can't search for them".into());
+        }
+        scenario.insert("attack".to_owned(), adversarial.attack.clone());
+    }
+    let params = match &config.adversarial {
+        _ if !config.params.is_empty() => config.params,
+        Some(adversarial) => adversarial.parameters(&scenario, FULL_DURATION)?,
+        None => default_parameters(),
+    };
+    // Fail before simulating anything if a metric is unknown
+    config.fitness.build()?;
+    // Start the search around the default value of each parameter
+    let master_seed = config
+        .master_seed
+        .unwrap_or_else(|| rand::thread_rng().gen());
+    let initial: Vec<f64> = params.iter().map(|p| p.initial()).collect();
+    let mut backend = config.optimizer.build(
+        &initial,
+        workers,
+        &mut StdRng::seed_from_u64(crate::util::derive_seed(master_seed, DESIGN_STREAM)),
+    )?;
+
+    let priors = match &config.warm_start {
+        Some(warm_start) => warm_start.load(
+            &params,
+            FULL_DURATION,
+            &default_scenario(),
+            &mut StdRng::seed_from_u64(crate::util::derive_seed(master_seed, WARM_START_STREAM)),
+        )?,
+        None => Vec::new(),
+    };
+    tell_priors(backend.as_mut(), &params, &priors)?;
+
+    let fidelity = match config.min_duration {
+        Some(min_duration) => Some(SuccessiveHalving::new(
+            min_duration,
+            FULL_DURATION,
+            config.reduction_factor,
+            config.hyperband,
+        )?),
+        None if config.hyperband => return Err("Hyperband needs a minimum duration".into()),
+        None => None,
+    };
+
+    Ok(StateImpl {
+        optimizer: config.optimizer,
+        optimizer_state: serde_json::Value::Null,
+        params,
+        scenario,
+        priors,
+        master_seed: Some(master_seed),
+        results: Vec::new(),
+        pareto_front: Vec::new(),
+        replicates: config.replicates.max(1),
+        fidelity,
+        robust: config.robust,
+        adversarial: config.adversarial,
+        constraints: config.constraints,
+        fitness: config.fitness,
+        stop_reason: None,
+        stopping: StoppingRules::default(),
+        pending: Vec::new(),
+        next_pending_id: 0,
+        backend: Some(backend),
+        refinement: None,
+        checkpoint: None,
+    })
+}
+
+/// Loads the state of an earlier campaign from its output json so that it can be continued.
+/// The optimizer restores its serialized state if it can, otherwise every finished run is
+/// replayed into it
+fn resume_state(json_path: &str, workers: usize) -> Result<StateImpl, crate::Error> {
+    let json = std::fs::read_to_string(json_path)?;
+    let mut state: StateImpl = serde_json::from_str(&json)?;
+
+    let master_seed = *state
+        .master_seed
+        .get_or_insert_with(|| rand::thread_rng().gen());
+    let initial: Vec<f64> = state.params.iter().map(|p| p.initial()).collect();
+    let mut backend = state.optimizer.build(
+        &initial,
+        workers,
+        &mut StdRng::seed_from_u64(crate::util::derive_seed(master_seed, DESIGN_STREAM)),
+    )?;
+    if backend.restore_state(&state.optimizer_state)? {
+        println!("Restored {:?} optimizer state", state.optimizer);
+    } else {
+        tell_priors(backend.as_mut(), &state.params, &state.priors)?;
+        let penalty = state.penalty();
+        let mut replayed = 0;
+        // Feasible screening runs are not told to the optimizer while running either
+        for run in state
+            .results
+            .iter()
+            .filter(|r| !r.is_feasible() || r.duration == FULL_DURATION)
+        {
+            if let Some(point) = normalized_point(&state.params, &run.parameters) {
+                if run.is_feasible() {
+                    let objectives: Vec<f64> = run.objectives.values().copied().collect();
+                    backend.tell_objectives(&point, run.error, &objectives)?;
+                } else {
+                    backend.tell_infeasible(&point, penalty)?;
+                }
+                replayed += 1;
+            }
+        }
+        println!(
+            "Replayed {} runs and {} earlier runs into {:?} optimizer",
+            replayed,
+            state.priors.len(),
+            state.optimizer
+        );
+    }
+    state.backend = Some(backend);
+
+    let best_error = state
+        .results
+        .iter()
+        .filter(|r| r.duration == FULL_DURATION && r.is_feasible())
+        .map(|r| r.error)
+        .fold(LOWEST_ERROR.load(Ordering::Relaxed), f64::min);
+    LOWEST_ERROR.store(best_error, Ordering::Relaxed);
+
+    println!(
+        "Resuming from {} with {} finished runs",
+        json_path,
+        state.results.len()
+    );
+    Ok(state)
+}
+
+/// Returns the number of seconds since the unix epoch, which is used to name output files
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+pub fn run(path: &str, config: Config) {
     ctrlc::set_handler(|| {
         static FORCE_EXIT: AtomicUsize = AtomicUsize::new(0);
         let count = FORCE_EXIT.fetch_add(1, Ordering::Relaxed);
@@ -83,60 +1165,158 @@ pub fn run(path: &str) {
     })
     .expect("failed to to set Control-C handler");
 
-    let _ = STATE.set(Arc::new(Mutex::new(StateImpl {
-        params: vec![
-            Parameter {
-                name: "a".to_owned(),
-                optim: tpe::TpeOptimizer::new(
-                    tpe::parzen_estimator(),
-                    tpe::range(PARAM_MIN, PARAM_MAX).unwrap(),
-                ),
-            },
-            Parameter {
-                name: "r".to_owned(),
-                optim: tpe::TpeOptimizer::new(
-                    tpe::parzen_estimator(),
-                    tpe::range(PARAM_MIN, PARAM_MAX).unwrap(),
-                ),
-            },
-        ],
-        results: Vec::new(),
-    })));
-    let default_error = LOWEST_ERROR.load(Ordering::Relaxed);
-    for param in STATE.get().unwrap().lock().unwrap().params.iter_mut() {
-        // Fill in default values so parameters start around 1 by default
-        param.optim.tell(1.0, default_error).unwrap();
+    let workers = config.workers.unwrap_or_else(num_cpus::get).max(1);
+    let checkpoint = Checkpointer {
+        path: PathBuf::from(format!("checkpoint-{}.json", unix_time())),
+        every: Some(config.checkpoint_every).filter(|every| *every > 0),
+        interval: Some(config.checkpoint_minutes)
+            .filter(|minutes| *minutes != 0.0)
+            .map(crate::stopping::minutes)
+            .transpose()
+            .expect("Bad checkpoint interval"),
+        last_simulations: 0,
+        last_time: Instant::now(),
+    };
+    let stopping = config.stopping.clone();
+    let (refine, refine_simulations) = (config.refine, config.refine_simulations);
+    let mut state = match config.resume.as_deref() {
+        Some(json_path) => {
+            resume_state(json_path, workers).expect("Failed to resume from previous run")
+        }
+        None => new_state(config, workers).expect("Failed to create optimizer"),
+    };
+    for param in &state.params {
+        println!("Optimizing {}", param);
     }
-
-    let mut threads = Vec::new();
-    let _ = PATH.set(path.to_owned());
-    for _ in 0..num_cpus::get() {
-        //for _ in 0..1 {
-        threads.push(std::thread::spawn(run_thread));
+    for (name, value) in &state.scenario {
+        println!("Simulating with {}={}", name, value);
     }
-    println!("Runners started");
-    for thread in threads {
-        let _ = thread.join();
+    println!("Using {:?} optimizer", state.optimizer);
+    println!("Using master seed {}", state.master_seed.unwrap());
+    if state.replicates > 1 {
+        println!("Simulating each point with {} seeds", state.replicates);
+    }
+    if let Some(fidelity) = &state.fidelity {
+        println!("Screening with durations {:?}", fidelity.durations());
+    }
+    for constraint in &state.constraints {
+        println!("Constraining {}", constraint);
+    }
+    for (name, weight) in &state.fitness.weights {
+        println!("Weighting {} by {}", name, weight);
+    }
+    for name in &state.fitness.record {
+        println!("Recording {}", name);
+    }
+    if let Some(robust) = &state.robust {
+        for scenario in &robust.scenarios {
+            println!(
+                "Scoring the {:?} of attack scenario {} {:?}",
+                robust.aggregation, scenario.name, scenario.arguments
+            );
+        }
     }
+    let _ = STARTED.set(Instant::now());
+    state.stopping = stopping;
+    state.stop_reason = None;
+    if let Some(reason) = state.check_stopping() {
+        println!(
+            "Not starting after {} simulations because the campaign {}",
+            state.results.len(),
+            reason
+        );
+        RUNNING.store(false, Ordering::Relaxed);
+    }
+    state.checkpoint = Some(Checkpointer {
+        last_simulations: state.results.len(),
+        ..checkpoint
+    });
+    let _ = STATE.set(Arc::new(Mutex::new(state)));
+    let _ = PATH.set(path.to_owned());
 
-    println!("All runners stopped");
-    let state = STATE.get().unwrap().lock().unwrap();
+    run_runners(workers);
+    // A runner that panicked while holding the lock poisons it, but the results are still good
+    let mut state = STATE
+        .get()
+        .unwrap()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    // Every other way of stopping records its reason
+    let reason = *state.stop_reason.get_or_insert(StopReason::Interrupted);
+    println!("Stopped because the campaign {}", reason);
+
+    if refine > 0 && reason != StopReason::Interrupted {
+        match state.start_refinement(refine, refine_simulations) {
+            Ok(()) => {
+                drop(state);
+                RUNNING.store(true, Ordering::Relaxed);
+                run_runners(workers);
+                state = STATE
+                    .get()
+                    .unwrap()
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let reason = *state.stop_reason.get_or_insert(StopReason::Interrupted);
+                println!("Stopped refining because the campaign {}", reason);
+            }
+            Err(err) => println!("Skipping refinement: {}", err),
+        }
+    }
     println!("Exporting results from {} simulations", state.results.len());
 
-    let json = serde_json::to_string(state.deref()).unwrap();
-    let now = SystemTime::now();
-    let delta = now
-        .duration_since(std::time::SystemTime::UNIX_EPOCH)
-        .unwrap();
-    std::fs::write(format!("output-{}.json", delta.as_secs()), json)
+    let output_path = format!("output-{}.json", unix_time());
+    state
+        .write_json(Path::new(&output_path))
         .expect("Failed to write stats to file");
     println!("Wrote data backup file");
 
     write_hot_cold(&state, "hot_cold.png").unwrap();
     write_error_time(&state, "error_time.png").unwrap();
+    write_pareto_front(&state, "pareto_front.png").unwrap();
+    write_model_surface(&state, "gp_surface.json").unwrap();
+    write_worst_attacks(&state, "worst_attacks.json").unwrap();
+    // run_analysis keeps the positions of each new best run
+    let best_positions = Path::new(path)
+        .join("out")
+        .join(format!("{}.csv", LOWEST_ERROR.load(Ordering::Relaxed)));
+    if best_positions.exists() {
+        write_convergence(&state.fitness, &best_positions, "convergence.png").unwrap();
+    }
+    if state.is_sensitivity_analysis() {
+        write_sensitivity(&state, "sensitivity.json", "sensitivity.png").unwrap();
+    }
+}
+
+/// Runs simulations on `workers` threads until `RUNNING` is cleared
+fn run_runners(workers: usize) {
+    // The runners of later phases and resumed campaigns start after the finished runs, so they
+    // don't repeat the seeds of earlier runners
+    let phase_seed = {
+        let state = STATE.get().unwrap().lock().unwrap();
+        crate::util::derive_seed(
+            crate::util::derive_seed(state.master_seed.unwrap(), RUNNER_STREAM),
+            state.results.len() as u64,
+        )
+    };
+    let mut threads = Vec::new();
+    for worker in 0..workers {
+        let seed = crate::util::derive_seed(phase_seed, worker as u64);
+        threads.push(std::thread::spawn(move || run_thread(seed)));
+    }
+    println!("Runners started");
+    for thread in threads {
+        let _ = thread.join();
+    }
+    println!("All runners stopped");
 }
 
-pub fn re_export(json_path: impl AsRef<Path>, prefix: Option<&str>) -> Result<(), crate::Error> {
+/// Writes the graphs of the campaign stored in `json_path`, and a graph of each of `terms`
+/// against the parameters
+pub fn re_export(
+    json_path: impl AsRef<Path>,
+    prefix: Option<&str>,
+    terms: &[String],
+) -> Result<(), crate::Error> {
     let json = std::fs::read_to_string(json_path)?;
     let state: StateImpl = serde_json::from_str(&json)?;
     if state.results.len() < 1000 {
@@ -152,11 +1332,34 @@ pub fn re_export(json_path: impl AsRef<Path>, prefix: Option<&str>) -> Result<()
     let error_time_path = format!("{}error_time.png", prefix.unwrap_or(""));
     write_error_time(&state, &error_time_path)?;
 
+    let pareto_front_path = format!("{}pareto_front.png", prefix.unwrap_or(""));
+    write_pareto_front(&state, &pareto_front_path)?;
+
+    let surface_path = format!("{}gp_surface.json", prefix.unwrap_or(""));
+    write_model_surface(&state, &surface_path)?;
+
+    let attacks_path = format!("{}worst_attacks.json", prefix.unwrap_or(""));
+    write_worst_attacks(&state, &attacks_path)?;
+
+    for term in terms {
+        let term_path = format!("{}term_{}.png", prefix.unwrap_or(""), term);
+        write_term(&state, term, &term_path)?;
+    }
+
+    if state.is_sensitivity_analysis() {
+        let sensitivity_path = format!("{}sensitivity", prefix.unwrap_or(""));
+        write_sensitivity(
+            &state,
+            &format!("{}.json", sensitivity_path),
+            &format!("{}.png", sensitivity_path),
+        )?;
+    }
+
     println!("Exported {} runs successfully", state.results.len());
     Ok(())
 }
 
-pub fn re_export_all(dir_path: impl AsRef<Path>) -> Result<(), crate::Error> {
+pub fn re_export_all(dir_path: impl AsRef<Path>, terms: &[String]) -> Result<(), crate::Error> {
     let path = dir_path.as_ref();
     println!("Checking {:?} for json files", path.to_str());
     for entry in walkdir::WalkDir::new(dir_path)
@@ -173,7 +1376,7 @@ pub fn re_export_all(dir_path: impl AsRef<Path>) -> Result<(), crate::Error> {
     {
         if entry.file_type().is_file() {
             let parent = entry.path().parent().expect("json file has no parent!");
-            if let Err(err) = re_export(entry.path(), parent.to_str()) {
+            if let Err(err) = re_export(entry.path(), parent.to_str(), terms) {
                 println!(
                     "Failed to export {}: {:?}",
                     entry.path().to_str().unwrap(),
@@ -188,9 +1391,12 @@ pub fn re_export_all(dir_path: impl AsRef<Path>) -> Result<(), crate::Error> {
 }
 
 /// Returns the axis ranges for a set of points of which points within `range_include` standard
-/// deviations of the mean are within the range
+/// deviations of the mean are within the range. The ranges never extend outside the bounds of
+/// the x and y parameters
 fn get_bounds_and_regression(
     points: &[(f64, f64, f64)], //(x, y, error)
+    x_param: &Parameter,
+    y_param: &Parameter,
     range_include: f64,
 ) -> (Range<f64>, Range<f64>, f64, f64) {
     //Clone points so we can work with a sorted version
@@ -232,11 +1438,11 @@ fn get_bounds_and_regression(
         rgsl::fit::wlinear(&x_coords, 1, &weight, 1, &y_coords, 1, best_count);
     println!("Got y={}x + {}, r^2={}", m, b, r_squared);
     dbg!(aa, b, m, bb, cc, dd, r_squared);
-    let x_min = (x_mean - x_stddev * range_include).max(PARAM_MIN);
-    let x_max = (x_mean + x_stddev * range_include).min(PARAM_MAX);
+    let x_min = (x_mean - x_stddev * range_include).max(x_param.min);
+    let x_max = (x_mean + x_stddev * range_include).min(x_param.max);
 
-    let y_min = (y_mean - y_stddev * range_include).max(PARAM_MIN);
-    let y_max = (y_mean + y_stddev * range_include).min(PARAM_MAX);
+    let y_min = (y_mean - y_stddev * range_include).max(y_param.min);
+    let y_max = (y_mean + y_stddev * range_include).min(y_param.max);
     println!(
         "Bounds are x= {}..{}, y= {}..{}",
         x_min, x_max, y_min, y_max
@@ -252,12 +1458,21 @@ fn get_bounds_and_regression(
 }
 
 fn write_hot_cold(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut error_scores: Vec<f64> = state
-        .results
-        .iter()
-        .map(|r| r.error)
-        .filter(|v| !v.is_nan())
+    // Screening runs from multi-fidelity mode would distort the colors
+    let results: Vec<&SimulationRun> = state
+        .longest_runs()
+        .into_iter()
+        .filter(|r| r.is_feasible())
         .collect();
+    if results.is_empty() {
+        println!("No feasible runs, skipping hot cold plot");
+        return Ok(());
+    }
+    // With a single parameter there is no second axis, so the error goes on it instead
+    if state.params.len() < 2 {
+        return write_term(state, "fitness", file_name);
+    }
+    let mut error_scores: Vec<f64> = results.iter().map(|r| r.error).collect();
 
     error_scores.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
@@ -273,10 +1488,9 @@ fn write_hot_cold(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std:
     let smoother = crate::util::RangeSmoother::new(step_size, error_scores.as_slice());
     let smoothed_values: Vec<_> = smoother.ranges().collect();
 
-    let mut params_to_draw: Vec<&String> = state.results[0].parameters.keys().take(2).collect();
+    let mut params_to_draw: Vec<&String> = results[0].parameters.keys().take(2).collect();
     params_to_draw.sort_by(|a, b| b.cmp(a));
-    let points: Vec<_> = state
-        .results
+    let points: Vec<_> = results
         .iter()
         .map(|result| {
             let params_used = &result.parameters;
@@ -301,8 +1515,12 @@ fn write_hot_cold(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std:
 
     const INCLUDE_POINTS_STDDEVS: f64 = 1.0;
     let areas = root.split_by_breakpoints([944], [80]);
-    let (x_bounds, y_bounds, linear_m, linear_b) =
-        get_bounds_and_regression(&points, INCLUDE_POINTS_STDDEVS);
+    let (x_bounds, y_bounds, linear_m, linear_b) = get_bounds_and_regression(
+        &points,
+        &state.param(x_param),
+        &state.param(y_param),
+        INCLUDE_POINTS_STDDEVS,
+    );
 
     let regression_func = |x: f64| -> f64 {
         let y = linear_m * x + linear_b;
@@ -313,7 +1531,7 @@ fn write_hot_cold(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std:
     let mut scatter_ctx = ChartBuilder::on(&areas[2])
         .x_label_area_size(60)
         .y_label_area_size(80)
-        .build_cartesian_2d(x_bounds, y_bounds)?;
+        .build_cartesian_2d(x_bounds.clone(), y_bounds.clone())?;
 
     scatter_ctx
         .configure_mesh()
@@ -325,6 +1543,39 @@ fn write_hot_cold(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std:
         .axis_desc_style(("sans-serif", 30))
         .draw()?;
 
+    let color_of = |error: f64| {
+        let mut i = 0;
+        for limit in smoothed_values.iter() {
+            i += 1;
+            if error < *limit {
+                break;
+            }
+        }
+        plotters::style::RGBColor(i as u8, (256 - i) as u8, 50)
+    };
+
+    // Shade the background with the model's prediction when there is one
+    let cells = 60;
+    if let Some(surface) =
+        state.model_surface(x_param, y_param, x_bounds.clone(), y_bounds.clone(), cells)?
+    {
+        let half_width = (x_bounds.end - x_bounds.start) / (cells - 1) as f64 / 2.0;
+        let half_height = (y_bounds.end - y_bounds.start) / (cells - 1) as f64 / 2.0;
+        scatter_ctx.draw_series(surface.y.iter().enumerate().flat_map(|(row, y)| {
+            let surface = &surface;
+            surface.x.iter().enumerate().map(move |(column, x)| {
+                let color = color_of(surface.mean[row][column]);
+                Rectangle::new(
+                    [
+                        (x - half_width, y - half_height),
+                        (x + half_width, y + half_height),
+                    ],
+                    color.mix(0.3).filled(),
+                )
+            })
+        }))?;
+    }
+
     scatter_ctx
         .draw_series(LineSeries::new(
             (-50..=50).map(|x| x as f64).map(|x| (x, (x / 10.0).sqrt())),
@@ -333,24 +1584,464 @@ fn write_hot_cold(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std:
         ))?
         .label("Average error");
 
-    scatter_ctx.draw_series(points.iter().map(|(x, y, error)| {
-        let mut i = 0;
-        for limit in smoothed_values.iter() {
-            i += 1;
-            if error < limit {
-                break;
-            }
+    scatter_ctx.draw_series(
+        points
+            .iter()
+            .map(|(x, y, error)| Circle::new((*x, *y), 2, color_of(*error).filled())),
+    )?;
+    // Infeasible runs of every duration show where the optimizer was told not to go
+    let infeasible = state.results.iter().filter(|r| !r.is_feasible());
+    scatter_ctx.draw_series(infeasible.map(|r| {
+        let (x, y) = (r.parameters[x_param], r.parameters[y_param]);
+        Cross::new((x, y), 3, ShapeStyle::from(&BLACK.mix(0.5)))
+    }))?;
+
+    root.present().expect("Unable to write image to file");
+
+    Ok(())
+}
+
+/// Writes the Gaussian process's mean and variance over the full range of the first two
+/// parameters, if the campaign used the `gp` optimizer
+fn write_model_surface(state: &StateImpl, file_name: &str) -> Result<(), crate::Error> {
+    if state.params.len() < 2 {
+        return Ok(());
+    }
+    let (x_param, y_param) = (&state.params[0], &state.params[1]);
+    if let Some(surface) = state.model_surface(
+        &x_param.name,
+        &y_param.name,
+        x_param.min..x_param.max,
+        y_param.min..y_param.max,
+        SURFACE_STEPS,
+    )? {
+        std::fs::write(file_name, serde_json::to_string_pretty(&surface)?)?;
+    }
+    Ok(())
+}
+
+/// Writes the most damaging attacks of an attack campaign and prints them. Does nothing for other
+/// campaigns
+fn write_worst_attacks(state: &StateImpl, file_name: &str) -> Result<(), crate::Error> {
+    let adversarial = match &state.adversarial {
+        Some(adversarial) => adversarial,
+        None => return Ok(()),
+    };
+    let attacks: Vec<WorstAttack> = state
+        .results
+        .iter()
+        .filter(|r| r.duration == FULL_DURATION)
+        .map(|r| WorstAttack {
+            attack: adversarial.attack.clone(),
+            parameters: r.parameters.clone(),
+            formation_error: -r.error,
+            confidence: r.error_confidence,
+        })
+        .collect();
+    let worst = crate::adversarial::worst_attacks(attacks, crate::adversarial::REPORTED_ATTACKS);
+    println!("Most damaging {} attacks:", adversarial.attack);
+    for attack in &worst {
+        println!(
+            "  error {:.3} ± {:.3} with {:?}",
+            attack.formation_error, attack.confidence, attack.parameters
+        );
+    }
+    std::fs::write(file_name, serde_json::to_string_pretty(&worst)?)?;
+    Ok(())
+}
+
+/// Plots every pair of objectives against each other, with the runs on the Pareto front
+/// highlighted
+fn write_pareto_front(
+    state: &StateImpl,
+    file_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let front = state.compute_pareto_front();
+    if front.is_empty() {
+        println!("No objectives recorded, skipping Pareto front");
+        return Ok(());
+    }
+    let names: Vec<&String> = state.results[front[0]].objectives.keys().collect();
+    let pairs: Vec<(usize, usize)> = (0..names.len())
+        .flat_map(|a| ((a + 1)..names.len()).map(move |b| (a, b)))
+        .collect();
+    if pairs.is_empty() {
+        println!("Only one objective, skipping Pareto front");
+        return Ok(());
+    }
+
+    let points: Vec<Vec<f64>> = state
+        .results
+        .iter()
+        .filter(|r| r.is_feasible() && r.objectives.len() == names.len())
+        .map(|r| r.objectives.values().copied().collect())
+        .filter(|objectives: &Vec<f64>| objectives.iter().all(|v| !v.is_nan()))
+        .collect();
+    let front_points: Vec<Vec<f64>> = front
+        .iter()
+        .map(|i| state.results[*i].objectives.values().copied().collect())
+        .collect();
+
+    let root = BitMapBackend::new(file_name, (768 * pairs.len() as u32, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((1, pairs.len()));
+
+    // Show the whole front but cut off the worst runs so they don't squash it into a corner
+    let range = |objective: usize| -> Range<f64> {
+        let min = points
+            .iter()
+            .map(|p| p[objective])
+            .fold(f64::INFINITY, f64::min);
+        let front_max = front_points
+            .iter()
+            .map(|p| p[objective])
+            .fold(f64::NEG_INFINITY, f64::max);
+        let max = min + (front_max - min) * 2.0;
+        if max > min {
+            min..max
+        } else {
+            min..(min + 1.0)
         }
+    };
 
-        let color = plotters::style::RGBColor(i as u8, (256 - i) as u8, 50);
-        Circle::new((*x, *y), 2, color.filled())
-    }))?;
+    for ((x, y), area) in pairs.iter().zip(areas.iter()) {
+        let (x_range, y_range) = (range(*x), range(*y));
+        let mut chart = ChartBuilder::on(area)
+            .margin(20)
+            .x_label_area_size(60)
+            .y_label_area_size(80)
+            .build_cartesian_2d(x_range.clone(), y_range.clone())?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .x_desc(names[*x].as_str())
+            .y_desc(names[*y].as_str())
+            .label_style(("sans-serif", 20))
+            .axis_desc_style(("sans-serif", 25))
+            .draw()?;
+
+        let in_range = |p: &&Vec<f64>| x_range.contains(&p[*x]) && y_range.contains(&p[*y]);
+        chart.draw_series(
+            points
+                .iter()
+                .filter(in_range)
+                .map(|p| Circle::new((p[*x], p[*y]), 2, RGBColor(180, 180, 180).filled())),
+        )?;
+        chart.draw_series(
+            front_points
+                .iter()
+                .filter(in_range)
+                .map(|p| Circle::new((p[*x], p[*y]), 4, RED.filled())),
+        )?;
+    }
 
     root.present().expect("Unable to write image to file");
+    println!("Pareto front has {} runs", front.len());
 
     Ok(())
 }
 
+/// Computes the sensitivity indices of the error and of every error term from the design stored
+/// in the optimizer state, and writes them as json and as one bar chart per output
+fn write_sensitivity(
+    state: &StateImpl,
+    json_file: &str,
+    png_file: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let design = crate::optimizer::saved_design(&state.optimizer_state)?;
+    let objective_names: Vec<String> = state
+        .results
+        .iter()
+        .find(|r| !r.objectives.is_empty())
+        .map(|r| r.objectives.keys().cloned().collect())
+        .unwrap_or_default();
+
+    let mut outputs: IndexMap<String, Vec<Sensitivity>> = IndexMap::new();
+    outputs.insert("error".to_owned(), design.analyze(|e| e.error));
+    for (i, name) in objective_names.iter().enumerate() {
+        outputs.insert(
+            name.clone(),
+            design.analyze(|e| e.objectives.get(i).copied().unwrap_or(f64::NAN)),
+        );
+    }
+
+    let report: IndexMap<&String, IndexMap<&String, &Sensitivity>> = outputs
+        .iter()
+        .map(|(output, indices)| {
+            let by_param = state
+                .params
+                .iter()
+                .map(|p| &p.name)
+                .zip(indices.iter())
+                .collect();
+            (output, by_param)
+        })
+        .collect();
+    let json = serde_json::json!({
+        "method": design.method,
+        "simulations": design.evaluations.iter().filter(|e| e.is_some()).count(),
+        "outputs": report,
+    });
+    std::fs::write(json_file, serde_json::to_string_pretty(&json)?)?;
+
+    let root = BitMapBackend::new(png_file, (512 * outputs.len() as u32, 640)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((1, outputs.len()));
+    let (first_label, second_label) = match design.method {
+        Method::Sobol => ("First order", "Total"),
+        Method::Morris => ("mu*", "sigma"),
+    };
+
+    for ((output, indices), area) in outputs.iter().zip(areas.iter()) {
+        let bars: Vec<(f64, f64)> = indices
+            .iter()
+            .map(|index| match index {
+                Sensitivity::Sobol { first_order, total } => (*first_order, *total),
+                Sensitivity::Morris { mu_star, sigma } => (*mu_star, *sigma),
+            })
+            .map(|(a, b)| (finite_or_zero(a), finite_or_zero(b)))
+            .collect();
+        let max = bars.iter().map(|(a, b)| a.max(*b)).fold(0.0, f64::max);
+        let min = bars.iter().map(|(a, b)| a.min(*b)).fold(0.0, f64::min);
+        let top = if max > 0.0 { max * 1.1 } else { 1.0 };
+
+        let params: Vec<&String> = state.params.iter().map(|p| &p.name).collect();
+        let mut chart = ChartBuilder::on(area)
+            .caption(output, ("sans-serif", 25))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(60)
+            .build_cartesian_2d(-0.5..(params.len() as f64 - 0.5), min..top)?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .x_labels(params.len())
+            .x_label_formatter(&|x| {
+                let index = x.round();
+                if (x - index).abs() < 0.01 && index >= 0.0 && (index as usize) < params.len() {
+                    params[index as usize].clone()
+                } else {
+                    String::new()
+                }
+            })
+            .label_style(("sans-serif", 20))
+            .draw()?;
+
+        chart
+            .draw_series(bars.iter().enumerate().map(|(i, (first, _))| {
+                let x = i as f64;
+                Rectangle::new([(x - 0.35, 0.0), (x, *first)], BLUE.filled())
+            }))?
+            .label(first_label)
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], BLUE.filled()));
+        chart
+            .draw_series(bars.iter().enumerate().map(|(i, (_, second))| {
+                let x = i as f64;
+                Rectangle::new([(x, 0.0), (x + 0.35, *second)], RED.filled())
+            }))?
+            .label(second_label)
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], RED.filled()));
+        chart
+            .configure_series_labels()
+            .background_style(WHITE)
+            .border_style(BLACK)
+            .draw()?;
+    }
+
+    root.present().expect("Unable to write image to file");
+    println!("Wrote sensitivity of {} outputs", outputs.len());
+
+    Ok(())
+}
+
+/// Plots `term` against each parameter, with one chart per parameter. `term` is anything
+/// [`SimulationRun::value`] knows
+fn write_term(state: &StateImpl, term: &str, file_name: &str) -> Result<(), crate::Error> {
+    let results: Vec<&SimulationRun> = state
+        .longest_runs()
+        .into_iter()
+        .filter(|r| r.is_feasible())
+        .collect();
+    let points: Vec<(&SimulationRun, f64)> = results
+        .iter()
+        .filter_map(|r| Some((*r, r.value(term)?)))
+        .filter(|(_, value)| value.is_finite())
+        .collect();
+    if points.is_empty() {
+        let known = results
+            .first()
+            .map(|r| r.value_names().join(", "))
+            .unwrap_or_default();
+        println!(
+            "No runs recorded {}, skipping its graph. Known: {}",
+            term, known
+        );
+        return Ok(());
+    }
+    let min = points.iter().map(|(_, v)| *v).fold(f64::INFINITY, f64::min);
+    let max = points
+        .iter()
+        .map(|(_, v)| *v)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let y_range = if max > min {
+        min..max
+    } else {
+        min..(min + 1.0)
+    };
+
+    let root =
+        BitMapBackend::new(file_name, (768 * state.params.len() as u32, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((1, state.params.len()));
+    for (param, area) in state.params.iter().zip(areas.iter()) {
+        let mut chart = ChartBuilder::on(area)
+            .margin(20)
+            .x_label_area_size(60)
+            .y_label_area_size(80)
+            .build_cartesian_2d(param.min..param.max, y_range.clone())?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .x_desc(param.name.as_str())
+            .y_desc(term)
+            .label_style(("sans-serif", 20))
+            .axis_desc_style(("sans-serif", 25))
+            .draw()?;
+
+        chart.draw_series(points.iter().filter_map(|(run, value)| {
+            let x = *run.parameters.get(&param.name)?;
+            Some(Circle::new((x, *value), 2, BLACK.mix(0.6).filled()))
+        }))?;
+    }
+
+    root.present().expect("Unable to write image to file");
+    Ok(())
+}
+
+/// Plots how the central distance and the peripheral spacing of the simulation in the positions
+/// file `positions` settled, marking the reference, the settling band and the settling time
+pub fn write_convergence(
+    fitness: &FitnessConfig,
+    positions: &Path,
+    file_name: &str,
+) -> Result<(), crate::Error> {
+    let positions = std::fs::read_to_string(positions)?;
+    let mut data = SimulationData::parse(&positions)?;
+    let signals = fitness
+        .convergence("central_settling_time")
+        .signals(&mut data);
+
+    let root =
+        BitMapBackend::new(file_name, (1024, 768 * signals.len() as u32)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let areas = root.split_evenly((signals.len(), 1));
+    for (signal, area) in signals.iter().zip(areas.iter()) {
+        if signal.values.is_empty() {
+            continue;
+        }
+        let response = &signal.response;
+        let end = signal.values.last().unwrap().0 as f64;
+        let (low, high) = signal
+            .values
+            .iter()
+            .map(|(_, value)| *value)
+            .chain([
+                signal.reference - response.band,
+                signal.reference + response.band,
+            ])
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), v| {
+                (low.min(v), high.max(v))
+            });
+        let margin = ((high - low) * 0.05).max(0.1);
+        let mut chart = ChartBuilder::on(area)
+            .margin(20)
+            .caption(
+                format!(
+                    "{}: rise {:.1} s, settled at {:.1} s, overshoot {:.2} m, steady state \
+                     error {:.2} m",
+                    signal.name,
+                    response.rise_time,
+                    response.settling_time,
+                    response.overshoot,
+                    response.steady_state_error
+                ),
+                ("sans-serif", 22),
+            )
+            .x_label_area_size(60)
+            .y_label_area_size(80)
+            .build_cartesian_2d(0.0..end.max(1.0), (low - margin)..(high + margin))?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .x_desc("Time (s)")
+            .y_desc(format!("{} distance (m)", signal.name))
+            .label_style(("sans-serif", 20))
+            .axis_desc_style(("sans-serif", 25))
+            .draw()?;
+
+        let band_style = ShapeStyle::from(&BLUE.mix(0.4));
+        for offset in [-response.band, response.band] {
+            let y = signal.reference + offset;
+            chart.draw_series(LineSeries::new([(0.0, y), (end, y)], band_style))?;
+        }
+        chart
+            .draw_series(LineSeries::new(
+                [(0.0, signal.reference), (end, signal.reference)],
+                BLUE.stroke_width(2),
+            ))?
+            .label("Reference")
+            .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], BLUE));
+        chart
+            .draw_series(LineSeries::new(
+                signal
+                    .values
+                    .iter()
+                    .map(|(time, value)| (*time as f64, *value)),
+                &BLACK,
+            ))?
+            .label(signal.name)
+            .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], BLACK));
+        if response.settling_time.is_finite() {
+            let (bottom, top) = (low - margin, high + margin);
+            chart
+                .draw_series(LineSeries::new(
+                    [
+                        (response.settling_time, bottom),
+                        (response.settling_time, top),
+                    ],
+                    RED.stroke_width(2),
+                ))?
+                .label("Settled")
+                .legend(|(x, y)| PathElement::new([(x, y), (x + 20, y)], RED));
+        }
+        chart
+            .configure_series_labels()
+            .background_style(WHITE)
+            .border_style(BLACK)
+            .label_font(("sans-serif", 20))
+            .draw()?;
+    }
+
+    root.present().expect("Unable to write image to file");
+    Ok(())
+}
+
+/// Bars can't be drawn for indices that couldn't be estimated
+fn finite_or_zero(value: f64) -> f64 {
+    if value.is_finite() {
+        value
+    } else {
+        0.0
+    }
+}
+
 fn write_error_time(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn std::error::Error>> {
     let root = BitMapBackend::new(file_name, (1024, 768)).into_drawing_area();
     root.fill(&WHITE)?;
@@ -358,13 +2049,28 @@ fn write_error_time(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn st
         println!("No data to graph");
         return Ok(());
     }
-    let worst_error = state
+    // Attack campaigns store the formation error negated, so it is turned back for the graph
+    let formation_error = |r: &SimulationRun| {
+        if state.adversarial.is_some() {
+            -r.error
+        } else {
+            r.error
+        }
+    };
+    // Infeasible runs have no error to draw
+    let worst_error = match state
         .results
         .iter()
-        .map(|e| e.error)
+        .filter(|r| r.is_feasible())
+        .map(formation_error)
         .max_by(|a, b| a.partial_cmp(b).unwrap())
-        .unwrap() as f32
-        * 0.7; //Scale down to hide outliers
+    {
+        Some(error) => error as f32 * 0.7, //Scale down to hide outliers
+        None => {
+            println!("No feasible runs, skipping error time plot");
+            return Ok(());
+        }
+    };
 
     let start = state.results.first().unwrap().time;
     let end = state.results.last().unwrap().time;
@@ -387,20 +2093,50 @@ fn write_error_time(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn st
         .light_line_style(&WHITE)
         .draw()?;
 
-    chart.draw_series(state.results.iter().map(|r| {
-        let a = (seconds_since_start(&r.time) as f32, r.error as f32);
-        Circle::new(a, 2u32, &BLACK)
-    }))?;
+    // Runs of each fidelity level get their own color, with full length runs in black
+    let mut durations: Vec<f64> = state.results.iter().map(|r| r.duration).collect();
+    durations.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    durations.dedup();
+    for (i, duration) in durations.iter().enumerate() {
+        let color = if i == 0 {
+            BLACK
+        } else {
+            let (r, g, b) = Palette99::pick(i).rgb();
+            RGBColor(r, g, b)
+        };
+        chart
+            .draw_series(
+                state
+                    .results
+                    .iter()
+                    .filter(|r| r.duration == *duration && r.is_feasible())
+                    .map(|r| {
+                        let a = (
+                            seconds_since_start(&r.time) as f32,
+                            formation_error(r) as f32,
+                        );
+                        Circle::new(a, 2u32, color.filled())
+                    }),
+            )?
+            .label(format!("{} s simulations", duration))
+            .legend(move |(x, y)| Circle::new((x, y), 4u32, color.filled()));
+    }
 
+    let longest_runs: Vec<&SimulationRun> = state
+        .longest_runs()
+        .into_iter()
+        .filter(|r| r.is_feasible())
+        .collect();
     chart
         .draw_series(LineSeries::new(
-            state.results.chunks(num_cpus::get() * 3 / 2).map(|runs| {
+            longest_runs.chunks(num_cpus::get() * 3 / 2).map(|runs| {
                 let x = runs
                     .iter()
                     .map(|r| seconds_since_start(&r.time) as f32)
                     .sum::<f32>()
                     / runs.len() as f32;
-                let y = runs.iter().map(|r| r.error as f32).sum::<f32>() / runs.len() as f32;
+                let y =
+                    runs.iter().map(|r| formation_error(r) as f32).sum::<f32>() / runs.len() as f32;
 
                 (x, y)
             }),
@@ -408,6 +2144,15 @@ fn write_error_time(state: &StateImpl, file_name: &str) -> Result<(), Box<dyn st
         ))?
         .label("Average error");
 
+    if durations.len() > 1 {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE)
+            .border_style(BLACK)
+            .label_font(("sans-serif", 20))
+            .draw()?;
+    }
+
     Ok(())
 }
 
@@ -443,13 +2188,21 @@ fn run_binary(
     }
 }
 
-fn run_thread() {
-    let mut rng = rand::thread_rng();
-    let mut param_map = IndexMap::new();
+/// Runs simulations until `RUNNING` is cleared. Both the optimizer and the simulation seeds draw
+/// from a random number generator seeded with `runner_seed`
+fn run_thread(runner_seed: u64) {
+    let mut rng = StdRng::seed_from_u64(runner_seed);
     let mut args: Vec<String> = Vec::new();
-    for arg in BASE_ARGUMENTS.iter() {
-        args.push((*arg).to_owned());
+    {
+        let state = STATE.get().unwrap().lock().unwrap();
+        for (name, value) in &state.scenario {
+            //Parameters that are being optimized replace the fixed value
+            if !state.params.iter().any(|p| p.name == *name) {
+                args.push(format!("--{}={}", name, value));
+            }
+        }
     }
+    let base_arguments = args.len();
 
     while RUNNING.load(Ordering::Relaxed) {
         let pos_file_name: String = rand::thread_rng()
@@ -459,7 +2212,7 @@ fn run_thread() {
             .collect();
 
         //Keep base arguments
-        args.resize(BASE_ARGUMENTS.len(), String::new());
+        args.resize(base_arguments, String::new());
 
         let ns3_path = PATH.get().unwrap();
         let mut buf = PathBuf::from(ns3_path);
@@ -472,29 +2225,58 @@ fn run_thread() {
             &positions_file.to_str().unwrap()
         ));
 
-        let seed: usize = rng.gen();
+        let seed: u64 = rng.gen();
         args.push(format!("--seed={}", seed));
 
-        {
-            let mut state = STATE.get().unwrap().lock().unwrap();
-            param_map.clear();
-            for param in state.params.iter_mut() {
-                let value = param.optim.ask(&mut rng).unwrap();
-                param_map.insert(param.name.clone(), value);
-                args.push(format!("--{}={}", param.name, value));
+        let next = STATE
+            .get()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .next_evaluation(&mut rng, runner_seed);
+        // Panicking here would poison the lock for every other runner
+        let evaluation = match next {
+            Ok(evaluation) => evaluation,
+            Err(err) => {
+                println!("Stopping the campaign: {}", err);
+                RUNNING.store(false, Ordering::Relaxed);
+                break;
             }
         };
+        args.push(format!("--duration={}", evaluation.duration));
+        for (name, value) in &evaluation.parameters {
+            args.push(format!("--{}={}", name, value));
+        }
+        if let Some(attack) = &evaluation.attack {
+            for (name, value) in &attack.arguments {
+                args.push(format!("--{}={}", name, value));
+            }
+        }
 
         //Run simulation
         match run_binary(ns3_path, "build/scratch/non-ideal/non-ideal", &args) {
-            Ok(_) => match run_analysis(&positions_file, &param_map, &positions_file) {
+            Ok(_) => match run_analysis(&positions_file, &evaluation, seed) {
                 Ok(_) => {}
                 Err(err) => {
                     println!("Error while doing analysis: {}", err);
+                    STATE
+                        .get()
+                        .unwrap()
+                        .lock()
+                        .unwrap()
+                        .abandon_replicate(&evaluation, &format!("analysis failed: {}", err))
+                        .expect("Failed to record an infeasible run");
                 }
             },
             Err(err) => {
                 println!("Error while running waf: {}", err);
+                STATE
+                    .get()
+                    .unwrap()
+                    .lock()
+                    .unwrap()
+                    .abandon_replicate(&evaluation, &format!("simulation crashed: {}", err))
+                    .expect("Failed to record an infeasible run");
                 let _ = std::fs::remove_file(positions_file);
             }
         }
@@ -502,120 +2284,70 @@ fn run_thread() {
     println!("Runner exiting cleanly");
 }
 
-fn get_error(data: &mut SimulationData) -> f64 {
-    let time_step = 0.1;
-    let mut time = 0.0;
-    let mut last_poses = IndexMap::new();
-    let uavs = data.uavs.clone();
-    let central_node = uavs.iter().min().unwrap();
-
-    let mut all_central_distances = Vec::new();
-    let mut all_peripheral_distances = Vec::new();
-    let mut all_velocities = Vec::new();
-    while time <= data.simulation_length {
-        let mut central_distances: Vec<f64> = Vec::new();
-        let mut peripheral_distances: Vec<f64> = Vec::new();
-        let mut velocities: Vec<f64> = Vec::new();
-
-        let central_pos = data.pos_at_time(TimePoint(time), *central_node).unwrap();
-        for uav in &uavs {
-            if let Some(now_pos) = data.pos_at_time(TimePoint(time), *uav) {
-                match last_poses.get(uav) {
-                    None => {}
-                    Some((last_pos, last_time)) => {
-                        let pos_delta = now_pos - *last_pos;
-                        let time_delta = time - last_time;
-                        let velocity: Vec3A = pos_delta / time_delta;
-                        velocities.push(velocity.length() as f64);
-                    }
-                }
-                last_poses.insert(uav, (now_pos, time));
-                if uav != central_node {
-                    central_distances.push((now_pos - central_pos).length() as f64);
-                    for uav_2 in &uavs {
-                        if uav != uav_2 && uav_2 != central_node {
-                            //Calculate the distance between this node and every other peripheral node
-                            if let Some(now_2_pos) = data.pos_at_time(TimePoint(time), *uav_2) {
-                                peripheral_distances.push((now_2_pos - now_pos).length() as f64);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        let central_distances_mean =
-            rgsl::statistics::mean(&central_distances, 1, central_distances.len());
-        let peripheral_distances_mean =
-            rgsl::statistics::mean(&peripheral_distances, 1, peripheral_distances.len());
-
-        let mean_velocity = rgsl::statistics::mean(&velocities, 1, velocities.len());
-
-        all_central_distances.push(central_distances_mean);
-        all_velocities.push(mean_velocity);
-        all_peripheral_distances.push(peripheral_distances_mean);
-        //println!("T: {}, V: {}, D: {}", time, mean_velocity, mad_of_distance);
-
-        time += time_step;
-    }
-    let mean_velocity: f64 = all_velocities.iter().sum::<f64>() / all_velocities.len() as f64;
-
-    let mean_central_distance: f64 =
-        rgsl::statistics::mean(&all_central_distances, 1, all_central_distances.len());
-
-    let mad_of_peripheral_distance: f64 =
-        rgsl::statistics::absdev(&all_peripheral_distances, 1, all_peripheral_distances.len());
-
-    println!("mean central: {mean_central_distance}, c mad: {mad_of_peripheral_distance}");
-
-    let p_mad_cost = 400.0 * mad_of_peripheral_distance;
-    let central_distance_cost = 400.0 * (TARGET_DISTANCE - mean_central_distance).abs();
-    let velocity_cost = 250.0 * mean_velocity;
-
-    p_mad_cost + central_distance_cost + velocity_cost
-}
-
+/// Computes the error of a finished simulation and records it as a replicate of point `id`
 fn run_analysis(
     pos_path: &std::path::Path,
-    param_map: &IndexMap<String, f64>,
-    positions_file: &std::path::Path,
+    evaluation: &Evaluation,
+    seed: u64,
 ) -> Result<(), Box<dyn std::error::Error>> {
     //let start = Instant::now();
     let positions = String::from_utf8(std::fs::read(&pos_path)?)?;
     let mut data = SimulationData::parse(&positions)?;
-    let error = get_error(&mut data);
-    {
+    let fitness = STATE.get().unwrap().lock().unwrap().fitness.build()?;
+    let breakdown = fitness.evaluate(&mut data);
+    let replicate = Replicate {
+        seed,
+        error: breakdown.total,
+        objectives: breakdown.terms,
+        costs: breakdown.costs,
+        metrics: breakdown.metrics,
+        scenario: evaluation.attack.as_ref().map(|attack| attack.name.clone()),
+    };
+    let finished = {
         let mut state = STATE.get().unwrap().lock().unwrap();
-        for param in state.params.iter_mut() {
-            let value = param_map.get(&param.name).unwrap();
-            param.optim.tell(*value, error).unwrap();
+        let finished = state
+            .finish_replicate(evaluation.id, replicate)?
+            .map(|run| {
+                // Only feasible full length runs can be the best
+                let comparable = run.duration == FULL_DURATION && run.is_feasible();
+                (
+                    comparable,
+                    run.error,
+                    run.error_confidence,
+                    run.parameters.clone(),
+                )
+            });
+        if finished.is_some() {
+            state.checkpoint_if_due();
+            if let Some(reason) = state.check_stopping() {
+                println!("Exiting after {} runs: {}", state.results.len(), reason);
+                RUNNING.store(false, Ordering::Relaxed);
+            } else {
+                println!("  {:.0}", state.full_simulations());
+            }
         }
-        state.results.push(SimulationRun {
-            parameters: param_map.clone(),
-            time: SystemTime::now(),
-            error,
-        });
-        let simulations = state.results.len();
-        if simulations == MAX_SIMULATIONS {
-            println!("Exiting after {}", MAX_SIMULATIONS);
-            RUNNING.store(false, Ordering::Relaxed);
-        } else {
-            println!("  {}", simulations);
+        finished
+    };
+    // Shorter simulations have errors that can't be compared with full length ones
+    if let Some((true, error, confidence, param_map)) = finished {
+        let old_error = LOWEST_ERROR.load(Ordering::Relaxed);
+        if error < old_error {
+            //If multiple threads get in here we don't really care...
+            LOWEST_ERROR.store(error, Ordering::Relaxed);
+            // Keep the positions of the replicate that completed the point
+            let src = pos_path;
+            let mut dest = PathBuf::from(pos_path);
+            dest.pop(); //Pop positions csv file name
+            dest.push("out");
+            let _ = std::fs::create_dir_all(&dest);
+            dest.push(format!("{}.csv", error));
+            std::fs::copy(src, dest).unwrap();
+            println!(
+                "  got best error: {} ± {} for params: {:?}",
+                error, confidence, param_map
+            );
         }
     }
-    let old_error = LOWEST_ERROR.load(Ordering::Relaxed);
-    if error < old_error {
-        //If multiple threads get in here we don't really care...
-        LOWEST_ERROR.store(error, Ordering::Relaxed);
-        let src = positions_file;
-        let mut dest = PathBuf::from(positions_file);
-        dest.pop(); //Pop positions csv file name
-        dest.push("out");
-        let _ = std::fs::create_dir_all(&dest);
-        dest.push(format!("{}.csv", error));
-        std::fs::copy(src, dest).unwrap();
-        println!("  got best error: {} for params: {:?}", error, param_map);
-    }
 
     if let Some(err) = std::fs::remove_file(pos_path).err() {
         println!(
@@ -626,3 +2358,40 @@ fn run_analysis(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn re_export_infeasible_runs() {
+        let dir = std::env::temp_dir().join(format!("re_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let run = |a: f64, error: &str, infeasible: &str| {
+            format!(
+                r#"{{"parameters": {{"a": {}, "r": 2}}, "fitness": {}, "infeasible": {},
+                "time": {{"secs_since_epoch": {}, "nanos_since_epoch": 0}}}}"#,
+                a,
+                error,
+                infeasible,
+                1_700_000_000.0 + a
+            )
+        };
+        let json = format!(
+            r#"{{"params": [{{"name": "a", "min": 0, "max": 18}}, {{"name": "r", "min": 0, "max": 18}}],
+            "results": [{}, {}, {}]}}"#,
+            run(1.0, "3.0", "null"),
+            run(2.0, "null", r#""simulation crashed""#),
+            run(3.0, "5.0", "null")
+        );
+        let mut state: StateImpl = serde_json::from_str(&json).unwrap();
+        let path = dir.join("output.json");
+        state.write_json(&path).unwrap();
+
+        let prefix = format!("{}/", dir.to_str().unwrap());
+        re_export(&path, Some(&prefix), &["fitness".to_owned()]).unwrap();
+        assert!(dir.join("error_time.png").exists());
+        assert!(dir.join("term_fitness.png").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
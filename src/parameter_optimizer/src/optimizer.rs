@@ -0,0 +1,194 @@
+//! Search strategies used to pick the parameters for the next simulation.
+//!
+//! Every backend works inside the unit hypercube: each coordinate of a point is in `0.0..=1.0`
+//! and is mapped onto the real range of its parameter by the caller. This keeps the backends
+//! independent of what is actually being tuned.
+
+use crate::sensitivity::Design;
+
+use rand::RngCore;
+
+mod bayesian;
+mod cma_es;
+mod compass_search;
+mod grid_search;
+mod independent_tpe;
+mod multivariate_tpe;
+mod nsga2;
+mod random_search;
+mod sensitivity_design;
+
+pub use bayesian::{saved_model, Bayesian};
+pub use cma_es::CmaEs;
+pub use compass_search::CompassSearch;
+pub use grid_search::GridSearch;
+pub use independent_tpe::IndependentTpe;
+pub use multivariate_tpe::MultivariateTpe;
+pub use nsga2::Nsga2;
+pub use random_search::RandomSearch;
+pub use sensitivity_design::{saved_design, SensitivityDesign};
+
+/// The error given to the initial guess by backends that seed themselves with it. Large enough
+/// that any real run will beat it
+const INITIAL_ERROR: f64 = 10000.0;
+
+/// How many points each axis is split into when using grid search
+const GRID_STEPS_PER_AXIS: usize = 30;
+
+/// Base samples of a Sobol design, which takes `samples * (parameters + 2)` simulations
+const SOBOL_SAMPLES: usize = 64;
+
+/// Trajectories of a Morris design, which takes `trajectories * (parameters + 1)` simulations
+const MORRIS_TRAJECTORIES: usize = 20;
+
+/// The error that pending points are assumed to have. Lying that they are as bad as the worst
+/// run is the pessimistic form of the "constant liar" heuristic of Ginsbourger et al.
+fn liar_error(errors: impl Iterator<Item = f64>) -> f64 {
+    errors
+        .filter(|e| e.is_finite())
+        .fold(None, |worst: Option<f64>, e| {
+            Some(worst.map_or(e, |w| w.max(e)))
+        })
+        .unwrap_or(INITIAL_ERROR)
+}
+
+/// The error told to backends about infeasible points: worse than every feasible error in
+/// `errors`, so that they rank below any point that could be simulated
+pub fn infeasible_penalty(errors: impl Iterator<Item = f64>) -> f64 {
+    let worst = liar_error(errors);
+    worst + worst.abs().max(1.0) * 0.1
+}
+
+/// A search strategy which suggests points and learns from the error they produced
+pub trait Optimizer: Send {
+    /// Returns the next point to evaluate. Every coordinate is in the range `0.0..=1.0`
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error>;
+
+    /// Returns the next point to evaluate while the `pending` points are still being evaluated.
+    /// Backends that model the error pretend the pending points came back with the worst error
+    /// seen so far, so that parallel runners spread out instead of all trying the most promising
+    /// spot. By default `pending` is ignored
+    fn ask_pending(
+        &mut self,
+        rng: &mut dyn RngCore,
+        _pending: &[Vec<f64>],
+    ) -> Result<Vec<f64>, crate::Error> {
+        self.ask(rng)
+    }
+
+    /// Reports the error that was measured when evaluating `point`
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error>;
+
+    /// Reports the error along with the separate objectives it was combined from. Backends that
+    /// only minimize a single value use the error
+    fn tell_objectives(
+        &mut self,
+        point: &[f64],
+        error: f64,
+        _objectives: &[f64],
+    ) -> Result<(), crate::Error> {
+        self.tell(point, error)
+    }
+
+    /// Reports that `point` is infeasible: its simulations failed, its error was NaN or it broke a
+    /// constraint. `penalty` comes from [`infeasible_penalty`]. By default the point is told with
+    /// the penalty as its error, which teaches backends that model the error to steer away from
+    /// the region
+    fn tell_infeasible(&mut self, point: &[f64], penalty: f64) -> Result<(), crate::Error> {
+        self.tell(point, penalty)
+    }
+
+    /// Returns true once the backend has nothing left to suggest, such as a fixed design that has
+    /// been fully evaluated
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Returns a snapshot of the internal state of the optimizer so that it can be stored
+    /// alongside the results
+    fn save_state(&self) -> serde_json::Value;
+
+    /// Restores the state returned by [`Optimizer::save_state`]. Returns `false` if this
+    /// backend cannot restore itself, in which case the caller should replay every finished
+    /// run through [`Optimizer::tell`] instead
+    fn restore_state(&mut self, _state: &serde_json::Value) -> Result<bool, crate::Error> {
+        Ok(false)
+    }
+}
+
+/// The optimization backends that can be selected on the command line
+///
+/// Runs exported before the backend was recorded all used TPE, so it is the default
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, clap::ArgEnum, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptimizerKind {
+    /// One univariate TPE estimator per parameter
+    #[default]
+    Tpe,
+    /// A single TPE estimator over the whole parameter vector
+    TpeMultivariate,
+    /// Uniform random sampling
+    Random,
+    /// Exhaustive walk over a regular grid
+    Grid,
+    /// Covariance matrix adaptation evolution strategy, one generation per batch of workers
+    CmaEs,
+    /// Multi-objective search for the Pareto front of the error terms
+    Nsga2,
+    /// Gaussian process model with expected improvement. Makes the most of small budgets on a
+    /// few continuous parameters
+    Gp,
+    /// Sensitivity analysis with Sobol indices instead of optimization
+    Sobol,
+    /// Sensitivity analysis with Morris elementary effects instead of optimization
+    Morris,
+}
+
+impl OptimizerKind {
+    /// Creates a new optimizer of this kind. `initial` is a point in the unit hypercube that is
+    /// a reasonable first guess, which backends may use to bias their first suggestions.
+    /// `workers` is the number of simulations that will be evaluated in parallel and `rng` lays
+    /// out the points of sensitivity analysis designs
+    pub fn build(
+        self,
+        initial: &[f64],
+        workers: usize,
+        rng: &mut dyn RngCore,
+    ) -> Result<Box<dyn Optimizer>, crate::Error> {
+        Ok(match self {
+            OptimizerKind::Tpe => Box::new(IndependentTpe::new(initial)?),
+            OptimizerKind::TpeMultivariate => Box::new(MultivariateTpe::new(initial)),
+            OptimizerKind::Random => Box::new(RandomSearch::new(initial.len())),
+            OptimizerKind::Grid => Box::new(GridSearch::new(initial.len(), GRID_STEPS_PER_AXIS)),
+            OptimizerKind::CmaEs => Box::new(CmaEs::new(initial, workers)),
+            OptimizerKind::Nsga2 => Box::new(Nsga2::new(initial.len(), (workers * 2).max(16))),
+            OptimizerKind::Gp => Box::new(Bayesian::new(initial)),
+            OptimizerKind::Sobol => Box::new(SensitivityDesign::new(Design::sobol(
+                SOBOL_SAMPLES,
+                initial.len(),
+                rng,
+            ))),
+            OptimizerKind::Morris => Box::new(SensitivityDesign::new(Design::morris(
+                MORRIS_TRAJECTORIES,
+                initial.len(),
+                rng,
+            ))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn liar_error_is_worst_run() {
+        assert_eq!(liar_error([3.0, f64::NAN, 5.0, 4.0].iter().copied()), 5.0);
+        // Nothing to go on yet
+        assert_eq!(liar_error(std::iter::empty()), INITIAL_ERROR);
+        assert_eq!(liar_error([f64::INFINITY].iter().copied()), INITIAL_ERROR);
+        assert!(infeasible_penalty([3.0, 5.0].iter().copied()) > 5.0);
+    }
+}
@@ -0,0 +1,240 @@
+use super::Optimizer;
+use crate::gaussian_process::{expected_improvement, GaussianProcess, Hyperparameters};
+
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+
+/// Random points tried when maximizing the expected improvement
+const CANDIDATES: usize = 2000;
+
+/// How many of the best candidates are refined with a pattern search
+const REFINED_CANDIDATES: usize = 3;
+
+/// The kernel hyperparameters are fitted again once the observations have grown by this fraction
+const REFIT_GROWTH: f64 = 0.1;
+
+/// Bayesian optimization with a Gaussian process model and expected improvement.
+///
+/// The initial guess and a few random points are simulated first. After that every suggestion is
+/// the point where the model expects the biggest improvement over the best error so far, which
+/// balances refining the best region against exploring where the model is unsure. Points that are
+/// still being simulated are added to the model with its own prediction (the "kriging believer"),
+/// which removes the uncertainty there so parallel runners pick different points
+pub struct Bayesian {
+    initial: Vec<f64>,
+    observations: Vec<(Vec<f64>, f64)>,
+    /// Points of the initial design handed out so far
+    initial_asked: usize,
+    hyperparameters: Option<Hyperparameters>,
+    /// How many observations the hyperparameters were fitted on
+    fitted_on: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    observations: Vec<(Vec<f64>, f64)>,
+    initial_asked: usize,
+    hyperparameters: Option<Hyperparameters>,
+    fitted_on: usize,
+}
+
+impl Bayesian {
+    pub fn new(initial: &[f64]) -> Self {
+        Self {
+            initial: initial.to_vec(),
+            observations: Vec::new(),
+            initial_asked: 0,
+            hyperparameters: None,
+            fitted_on: 0,
+        }
+    }
+
+    /// Simulations before the model is trusted
+    fn initial_design(&self) -> usize {
+        (2 * self.initial.len() + 1).max(5)
+    }
+
+    /// Returns the model of the observations, fitting the hyperparameters again if enough has
+    /// been learned since the last time
+    fn model(&mut self, rng: &mut dyn RngCore) -> Result<GaussianProcess, crate::Error> {
+        let (points, values): (Vec<Vec<f64>>, Vec<f64>) = self.observations.iter().cloned().unzip();
+        match &self.hyperparameters {
+            Some(hyperparameters)
+                if (points.len() as f64) < self.fitted_on as f64 * (1.0 + REFIT_GROWTH) =>
+            {
+                GaussianProcess::fit(&points, &values, hyperparameters.clone())
+            }
+            start => {
+                let gp =
+                    GaussianProcess::fit_hyperparameters(&points, &values, start.as_ref(), rng)?;
+                self.hyperparameters = Some(gp.hyperparameters().clone());
+                self.fitted_on = points.len();
+                Ok(gp)
+            }
+        }
+    }
+}
+
+impl Optimizer for Bayesian {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        self.ask_pending(rng, &[])
+    }
+
+    fn ask_pending(
+        &mut self,
+        rng: &mut dyn RngCore,
+        pending: &[Vec<f64>],
+    ) -> Result<Vec<f64>, crate::Error> {
+        if self.initial_asked < self.initial_design() || self.observations.len() < 2 {
+            self.initial_asked += 1;
+            if self.initial_asked == 1 {
+                return Ok(self.initial.clone());
+            }
+            return Ok((0..self.initial.len()).map(|_| rng.gen()).collect());
+        }
+
+        let gp = self.model(rng)?;
+        let gp = if pending.is_empty() {
+            gp
+        } else {
+            let (mut points, mut values): (Vec<Vec<f64>>, Vec<f64>) =
+                self.observations.iter().cloned().unzip();
+            for point in pending {
+                values.push(gp.predict(point).0);
+                points.push(point.clone());
+            }
+            GaussianProcess::fit(&points, &values, gp.hyperparameters().clone())?
+        };
+        let best = self
+            .observations
+            .iter()
+            .map(|(_, error)| *error)
+            .fold(f64::INFINITY, f64::min);
+        let score = |x: &[f64]| {
+            let (mean, variance) = gp.predict(x);
+            expected_improvement(mean, variance, best)
+        };
+
+        let mut candidates: Vec<(Vec<f64>, f64)> = (0..CANDIDATES)
+            .map(|_| {
+                let x: Vec<f64> = (0..self.initial.len()).map(|_| rng.gen()).collect();
+                let s = score(&x);
+                (x, s)
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        candidates.truncate(REFINED_CANDIDATES);
+
+        // The improvement is cheap to evaluate, so polish the best candidates with a compass search
+        for (x, s) in candidates.iter_mut() {
+            let mut step = 0.05;
+            while step > 1e-3 {
+                let mut improved = false;
+                for i in 0..x.len() {
+                    for direction in &[step, -step] {
+                        let mut candidate = x.clone();
+                        candidate[i] = (candidate[i] + direction).clamp(0.0, 1.0);
+                        let candidate_score = score(&candidate);
+                        if candidate_score > *s {
+                            *x = candidate;
+                            *s = candidate_score;
+                            improved = true;
+                        }
+                    }
+                }
+                if !improved {
+                    step /= 2.0;
+                }
+            }
+        }
+        let (point, _) = candidates
+            .into_iter()
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .unwrap();
+        Ok(point)
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        // A failed simulation says nothing about the shape of the error
+        if error.is_finite() {
+            self.observations.push((point.to_vec(), error));
+        }
+        Ok(())
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(State {
+            observations: self.observations.clone(),
+            initial_asked: self.initial_asked,
+            hyperparameters: self.hyperparameters.clone(),
+            fitted_on: self.fitted_on,
+        })
+        .unwrap()
+    }
+
+    fn restore_state(&mut self, state: &serde_json::Value) -> Result<bool, crate::Error> {
+        let state = State::deserialize(state)?;
+        self.observations = state.observations;
+        self.initial_asked = state.initial_asked;
+        self.hyperparameters = state.hyperparameters;
+        self.fitted_on = state.fitted_on;
+        Ok(true)
+    }
+}
+
+/// Rebuilds the model from the saved state of a [`Bayesian`] optimizer. Returns `None` if too
+/// few runs finished to fit one
+pub fn saved_model(state: &serde_json::Value) -> Result<Option<GaussianProcess>, crate::Error> {
+    let state = State::deserialize(state)?;
+    if state.observations.len() < 2 {
+        return Ok(None);
+    }
+    let (points, values): (Vec<Vec<f64>>, Vec<f64>) = state.observations.into_iter().unzip();
+    let dimensions = points[0].len();
+    let hyperparameters = state
+        .hyperparameters
+        .unwrap_or_else(|| Hyperparameters::default_for(dimensions));
+    Ok(Some(GaussianProcess::fit(
+        &points,
+        &values,
+        hyperparameters,
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn finds_minimum_with_few_runs() {
+        let error = |p: &[f64]| 10.0 * ((p[0] - 0.3).powi(2) + (p[1] - 0.7).powi(2)) + 1.0;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut optim = Bayesian::new(&[0.5, 0.5]);
+        // Pairs of runners, to exercise the pending points
+        for _ in 0..15 {
+            let a = optim.ask(&mut rng).unwrap();
+            let b = optim
+                .ask_pending(&mut rng, std::slice::from_ref(&a))
+                .unwrap();
+            assert_ne!(a, b);
+            optim.tell(&a, error(&a)).unwrap();
+            optim.tell(&b, error(&b)).unwrap();
+        }
+
+        let best = optim
+            .observations
+            .iter()
+            .map(|(_, e)| *e)
+            .fold(f64::INFINITY, f64::min);
+        assert!(best < 1.01, "best error {}", best);
+
+        let mut restored = Bayesian::new(&[0.5, 0.5]);
+        assert!(restored.restore_state(&optim.save_state()).unwrap());
+        assert_eq!(restored.observations.len(), 30);
+        let model = saved_model(&optim.save_state()).unwrap().unwrap();
+        let (mean, _) = model.predict(&[0.3, 0.7]);
+        assert!((mean - 1.0).abs() < 0.1, "predicted {}", mean);
+    }
+}
@@ -0,0 +1,294 @@
+use super::Optimizer;
+
+use nalgebra::{DMatrix, DVector};
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+
+/// Initial step size. The search space is the unit hypercube so this covers a good part of it
+const INITIAL_SIGMA: f64 = 0.3;
+
+/// Covariance matrix adaptation evolution strategy.
+///
+/// A generation is `population` evaluations. Because the runner threads finish at different
+/// times, `ask` never blocks: it keeps sampling from the current distribution, and every told
+/// point is counted towards the current generation. Points sampled from an older distribution are
+/// injected by clipping their Mahalanobis length, as described by Hansen in "Injecting External
+/// Solutions Into CMA-ES" (2011)
+pub struct CmaEs {
+    dimensions: usize,
+    population: usize,
+    generation: usize,
+
+    mean: DVector<f64>,
+    sigma: f64,
+    covariance: DMatrix<f64>,
+    /// Evolution path for the covariance matrix
+    path_c: DVector<f64>,
+    /// Evolution path for the step size
+    path_sigma: DVector<f64>,
+
+    /// Eigen decomposition of `covariance`, so that `covariance = b * diag(d^2) * b^T`
+    b: DMatrix<f64>,
+    d: DVector<f64>,
+
+    /// Steps `(x - mean) / sigma` and errors told during the current generation
+    told: Vec<(DVector<f64>, f64)>,
+
+    weights: Vec<f64>,
+    mu_eff: f64,
+    c_sigma: f64,
+    d_sigma: f64,
+    c_c: f64,
+    c_1: f64,
+    c_mu: f64,
+    /// Expected length of a standard normally distributed vector
+    chi_n: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    population: usize,
+    generation: usize,
+    mean: Vec<f64>,
+    sigma: f64,
+    /// Stored row by row
+    covariance: Vec<Vec<f64>>,
+    path_c: Vec<f64>,
+    path_sigma: Vec<f64>,
+}
+
+impl CmaEs {
+    /// Creates a new CMA-ES instance centered at `initial` which evaluates `population` points
+    /// per generation
+    pub fn new(initial: &[f64], population: usize) -> Self {
+        let n = initial.len();
+        // Recombination needs at least two parents
+        let population = population.max(4);
+        let mu = population / 2;
+        let weights: Vec<f64> = (0..mu)
+            .map(|i| (mu as f64 + 0.5).ln() - ((i + 1) as f64).ln())
+            .collect();
+        let weight_sum: f64 = weights.iter().sum();
+        let weights: Vec<f64> = weights.iter().map(|w| w / weight_sum).collect();
+        let mu_eff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+
+        let n_f = n as f64;
+        let c_sigma = (mu_eff + 2.0) / (n_f + mu_eff + 5.0);
+        let d_sigma = 1.0 + 2.0 * (((mu_eff - 1.0) / (n_f + 1.0)).sqrt() - 1.0).max(0.0) + c_sigma;
+        let c_c = (4.0 + mu_eff / n_f) / (n_f + 4.0 + 2.0 * mu_eff / n_f);
+        let c_1 = 2.0 / ((n_f + 1.3).powi(2) + mu_eff);
+        let c_mu =
+            (1.0 - c_1).min(2.0 * (mu_eff - 2.0 + 1.0 / mu_eff) / ((n_f + 2.0).powi(2) + mu_eff));
+        let chi_n = n_f.sqrt() * (1.0 - 1.0 / (4.0 * n_f) + 1.0 / (21.0 * n_f * n_f));
+
+        Self {
+            dimensions: n,
+            population,
+            generation: 0,
+            mean: DVector::from_column_slice(initial),
+            sigma: INITIAL_SIGMA,
+            covariance: DMatrix::identity(n, n),
+            path_c: DVector::zeros(n),
+            path_sigma: DVector::zeros(n),
+            b: DMatrix::identity(n, n),
+            d: DVector::from_element(n, 1.0),
+            told: Vec::new(),
+            weights,
+            mu_eff,
+            c_sigma,
+            d_sigma,
+            c_c,
+            c_1,
+            c_mu,
+            chi_n,
+        }
+    }
+
+    /// Returns `covariance^(-1/2) * v`
+    fn inv_sqrt_covariance_mul(&self, v: &DVector<f64>) -> DVector<f64> {
+        let mut scaled = self.b.transpose() * v;
+        for (x, d) in scaled.iter_mut().zip(self.d.iter()) {
+            *x /= d;
+        }
+        &self.b * scaled
+    }
+
+    fn decompose_covariance(&mut self) {
+        // Keep the matrix exactly symmetric so rounding errors don't accumulate
+        self.covariance = (&self.covariance + self.covariance.transpose()) * 0.5;
+        let eigen = self.covariance.clone().symmetric_eigen();
+        self.b = eigen.eigenvectors;
+        self.d = eigen.eigenvalues.map(|e| e.max(1e-20).sqrt());
+    }
+
+    fn update(&mut self) {
+        let n = self.dimensions as f64;
+        self.told.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+        let mut step = DVector::zeros(self.dimensions);
+        for ((y, _), w) in self.told.iter().zip(self.weights.iter()) {
+            step += y * *w;
+        }
+
+        self.mean += &step * self.sigma;
+
+        let c_sigma = self.c_sigma;
+        self.path_sigma = &self.path_sigma * (1.0 - c_sigma)
+            + self.inv_sqrt_covariance_mul(&step)
+                * (c_sigma * (2.0 - c_sigma) * self.mu_eff).sqrt();
+
+        let path_sigma_norm = self.path_sigma.norm();
+        let h_sigma = path_sigma_norm
+            / (1.0 - (1.0 - c_sigma).powi(2 * (self.generation as i32 + 1))).sqrt()
+            < (1.4 + 2.0 / (n + 1.0)) * self.chi_n;
+        let h_sigma = if h_sigma { 1.0 } else { 0.0 };
+
+        let c_c = self.c_c;
+        self.path_c = &self.path_c * (1.0 - c_c)
+            + &step * (h_sigma * (c_c * (2.0 - c_c) * self.mu_eff).sqrt());
+
+        let mut rank_mu = DMatrix::zeros(self.dimensions, self.dimensions);
+        for ((y, _), w) in self.told.iter().zip(self.weights.iter()) {
+            rank_mu += y * y.transpose() * *w;
+        }
+        let rank_one = &self.path_c * self.path_c.transpose()
+            + &self.covariance * ((1.0 - h_sigma) * c_c * (2.0 - c_c));
+        self.covariance = &self.covariance * (1.0 - self.c_1 - self.c_mu)
+            + rank_one * self.c_1
+            + rank_mu * self.c_mu;
+
+        self.sigma *= ((c_sigma / self.d_sigma) * (path_sigma_norm / self.chi_n - 1.0)).exp();
+        // The whole search space has a width of one, so there is no point in searching wider
+        self.sigma = self.sigma.min(1.0);
+
+        self.decompose_covariance();
+        self.told.clear();
+        self.generation += 1;
+    }
+}
+
+impl Optimizer for CmaEs {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        let z = DVector::from_fn(self.dimensions, |_, _| {
+            rng.sample::<f64, _>(rand_distr::StandardNormal)
+        });
+        let y = &self.b * self.d.component_mul(&z);
+        let x = &self.mean + y * self.sigma;
+        Ok(x.iter().map(|v| v.clamp(0.0, 1.0)).collect())
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        if error.is_nan() {
+            return Err("Cannot tell a NaN error to the optimizer".into());
+        }
+        let n = self.dimensions as f64;
+        let mut y = (DVector::from_column_slice(point) - &self.mean) / self.sigma;
+        // Clip points which are unlikely under the current distribution, so that results from
+        // older generations cannot drag the mean too far
+        let max_length = n.sqrt() + 2.0 * n / (n + 2.0);
+        let length = self.inv_sqrt_covariance_mul(&y).norm();
+        if length > max_length {
+            y *= max_length / length;
+        }
+        self.told.push((y, error));
+
+        if self.told.len() >= self.population {
+            self.update();
+        }
+        Ok(())
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(State {
+            population: self.population,
+            generation: self.generation,
+            mean: self.mean.iter().copied().collect(),
+            sigma: self.sigma,
+            covariance: self
+                .covariance
+                .row_iter()
+                .map(|row| row.iter().copied().collect())
+                .collect(),
+            path_c: self.path_c.iter().copied().collect(),
+            path_sigma: self.path_sigma.iter().copied().collect(),
+        })
+        .unwrap()
+    }
+
+    fn restore_state(&mut self, state: &serde_json::Value) -> Result<bool, crate::Error> {
+        let state = State::deserialize(state)?;
+        let n = self.dimensions;
+        if state.mean.len() != n
+            || state.path_c.len() != n
+            || state.path_sigma.len() != n
+            || state.covariance.len() != n
+            || state.covariance.iter().any(|row| row.len() != n)
+        {
+            return Err(format!("Saved CMA-ES state does not have {} dimensions", n).into());
+        }
+        // The population follows the current number of workers rather than the saved one
+        self.generation = state.generation;
+        self.mean = DVector::from_vec(state.mean);
+        self.sigma = state.sigma;
+        let covariance = state.covariance;
+        self.covariance = DMatrix::from_fn(n, n, |row, column| covariance[row][column]);
+        self.path_c = DVector::from_vec(state.path_c);
+        self.path_sigma = DVector::from_vec(state.path_sigma);
+        self.told.clear();
+        self.decompose_covariance();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn converges_on_rotated_ellipse() {
+        // A narrow valley along the diagonal with its minimum at (0.6, 0.4)
+        let error = |p: &[f64]| {
+            let u = (p[0] - 0.6) + (p[1] - 0.4);
+            let v = (p[0] - 0.6) - (p[1] - 0.4);
+            u * u + 50.0 * v * v
+        };
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut optim = CmaEs::new(&[0.1, 0.9], 8);
+        // Imitate runner threads that finish out of order
+        let mut in_flight: Vec<Vec<f64>> = (0..8).map(|_| optim.ask(&mut rng).unwrap()).collect();
+        for _ in 0..400 {
+            let index = rng.gen_range(0..in_flight.len());
+            let point = in_flight.swap_remove(index);
+            optim.tell(&point, error(&point)).unwrap();
+            in_flight.push(optim.ask(&mut rng).unwrap());
+        }
+
+        assert!(optim.generation >= 40);
+        let mean: Vec<f64> = optim.mean.iter().copied().collect();
+        assert!(error(&mean) < 1e-4, "mean: {:?}", mean);
+        assert!(optim.sigma < 0.05, "sigma: {}", optim.sigma);
+    }
+
+    #[test]
+    fn restores_saved_state() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let mut optim = CmaEs::new(&[0.5, 0.5, 0.5], 6);
+        for _ in 0..30 {
+            let point = optim.ask(&mut rng).unwrap();
+            let error = point.iter().map(|x| (x - 0.2).powi(2)).sum();
+            optim.tell(&point, error).unwrap();
+        }
+
+        let mut restored = CmaEs::new(&[0.5, 0.5, 0.5], 6);
+        assert!(restored.restore_state(&optim.save_state()).unwrap());
+        assert_eq!(restored.generation, optim.generation);
+        assert_eq!(restored.mean, optim.mean);
+        assert_eq!(restored.sigma, optim.sigma);
+        assert_eq!(restored.save_state(), optim.save_state());
+
+        let mut wrong_size = CmaEs::new(&[0.5, 0.5], 6);
+        assert!(wrong_size.restore_state(&optim.save_state()).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use super::Optimizer;
+
+use rand::{Rng, RngCore};
+
+/// Step size of a new center, as a fraction of each parameter's range
+const INITIAL_STEP: f64 = 0.1;
+
+/// Centers stop polling once their step is smaller than this
+const MIN_STEP: f64 = 0.005;
+
+/// Parallel compass search (a pattern search) around several starting points at once.
+///
+/// Every iteration polls one step up and down every axis of a center. Once all polls are back the
+/// center moves to the best one if it beat the center, otherwise the step is halved. When every
+/// poll of every center is out, the idle runners poll random directions at the same step instead
+/// of waiting. Polls are clamped to the unit hypercube, so they never leave the parameter bounds
+pub struct CompassSearch {
+    centers: Vec<Center>,
+    next_center: usize,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct Center {
+    point: Vec<f64>,
+    error: f64,
+    step: f64,
+    /// Polls of the current iteration that haven't been handed out
+    #[serde(skip)]
+    queue: Vec<Vec<f64>>,
+    /// Polls of the current iteration that have been handed out but not told
+    #[serde(skip)]
+    outstanding: Vec<Vec<f64>>,
+    /// Random polls handed to idle runners. The iteration doesn't wait for them
+    #[serde(skip)]
+    extra: Vec<Vec<f64>>,
+    /// The best result of the current iteration
+    #[serde(skip)]
+    best_poll: Option<(Vec<f64>, f64)>,
+}
+
+impl Center {
+    fn new(point: Vec<f64>, error: f64) -> Self {
+        let mut center = Self {
+            point,
+            error,
+            step: INITIAL_STEP,
+            queue: Vec::new(),
+            outstanding: Vec::new(),
+            extra: Vec::new(),
+            best_poll: None,
+        };
+        center.start_iteration();
+        center
+    }
+
+    fn is_converged(&self) -> bool {
+        self.step < MIN_STEP
+    }
+
+    /// Queues the polls along every axis, skipping ones the bounds collapse onto the center
+    fn start_iteration(&mut self) {
+        self.queue.clear();
+        self.best_poll = None;
+        if self.is_converged() {
+            return;
+        }
+        for axis in 0..self.point.len() {
+            for direction in &[1.0, -1.0] {
+                let mut poll = self.point.clone();
+                poll[axis] = (poll[axis] + direction * self.step).clamp(0.0, 1.0);
+                if poll != self.point && !self.queue.contains(&poll) {
+                    self.queue.push(poll);
+                }
+            }
+        }
+        // Pop from the front so the axes are polled in order
+        self.queue.reverse();
+    }
+
+    /// Moves or shrinks once every poll of the iteration is back
+    fn finish_iteration(&mut self) {
+        if !self.queue.is_empty() || !self.outstanding.is_empty() {
+            return;
+        }
+        match self.best_poll.take() {
+            Some((point, error)) if error < self.error => {
+                self.point = point;
+                self.error = error;
+            }
+            _ => self.step /= 2.0,
+        }
+        self.start_iteration();
+    }
+}
+
+impl CompassSearch {
+    /// Starts a search around every point in `starts`, given with the error it is known to have
+    pub fn new(starts: Vec<(Vec<f64>, f64)>) -> Self {
+        assert!(!starts.is_empty(), "compass search needs a starting point");
+        Self {
+            centers: starts
+                .into_iter()
+                .map(|(point, error)| Center::new(point, error))
+                .collect(),
+            next_center: 0,
+        }
+    }
+
+    /// Returns the best point found so far and its error
+    pub fn best(&self) -> (&[f64], f64) {
+        let best = self
+            .centers
+            .iter()
+            .min_by(|a, b| a.error.partial_cmp(&b.error).unwrap())
+            .unwrap();
+        (&best.point, best.error)
+    }
+}
+
+impl Optimizer for CompassSearch {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        let count = self.centers.len();
+        // Take turns between the centers so they all make progress
+        let queued = (0..count)
+            .map(|offset| (self.next_center + offset) % count)
+            .find(|i| !self.centers[*i].queue.is_empty());
+        if let Some(index) = queued {
+            self.next_center = (index + 1) % count;
+            let center = &mut self.centers[index];
+            let poll = center.queue.pop().unwrap();
+            center.outstanding.push(poll.clone());
+            return Ok(poll);
+        }
+
+        let active: Vec<usize> = (0..count)
+            .filter(|i| !self.centers[*i].is_converged())
+            .collect();
+        if active.is_empty() {
+            // Finished, the runners are about to be stopped
+            return Ok(self.best().0.to_vec());
+        }
+        let center = &mut self.centers[active[rng.gen_range(0..active.len())]];
+        let direction: Vec<f64> = (0..center.point.len())
+            .map(|_| rng.sample::<f64, _>(rand_distr::StandardNormal))
+            .collect();
+        let length = direction
+            .iter()
+            .map(|d| d * d)
+            .sum::<f64>()
+            .sqrt()
+            .max(1e-12);
+        let poll: Vec<f64> = center
+            .point
+            .iter()
+            .zip(direction.iter())
+            .map(|(x, d)| (x + d / length * center.step).clamp(0.0, 1.0))
+            .collect();
+        center.extra.push(poll.clone());
+        Ok(poll)
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        for center in &mut self.centers {
+            let axis_poll = if let Some(index) = center.outstanding.iter().position(|p| p == point)
+            {
+                center.outstanding.swap_remove(index);
+                true
+            } else if let Some(index) = center.extra.iter().position(|p| p == point) {
+                center.extra.swap_remove(index);
+                false
+            } else {
+                continue;
+            };
+            if error < center.best_poll.as_ref().map_or(center.error, |b| b.1) {
+                center.best_poll = Some((point.to_vec(), error));
+            }
+            if axis_poll {
+                center.finish_iteration();
+            }
+            return Ok(());
+        }
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.centers.iter().all(Center::is_converged)
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::json!({ "centers": self.centers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn polishes_minimum() {
+        let error = |p: &[f64]| (p[0] - 0.63).powi(2) + 4.0 * (p[1] - 0.21).powi(2);
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let starts = vec![
+            (vec![0.5, 0.5], error(&[0.5, 0.5])),
+            (vec![0.9, 0.0], error(&[0.9, 0.0])),
+        ];
+        let mut optim = CompassSearch::new(starts);
+        // Imitate four runners that finish out of order
+        let mut in_flight: Vec<Vec<f64>> = (0..4).map(|_| optim.ask(&mut rng).unwrap()).collect();
+        let mut evaluations = 0;
+        while !optim.is_finished() {
+            let index = rng.gen_range(0..in_flight.len());
+            let point = in_flight.swap_remove(index);
+            assert!(point.iter().all(|x| (0.0..=1.0).contains(x)));
+            optim.tell(&point, error(&point)).unwrap();
+            in_flight.push(optim.ask(&mut rng).unwrap());
+            evaluations += 1;
+            assert!(evaluations < 2000);
+        }
+
+        let (best, best_error) = optim.best();
+        assert!(best_error < 1e-4, "{:?} {}", best, best_error);
+    }
+}
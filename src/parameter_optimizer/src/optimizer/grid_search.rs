@@ -0,0 +1,118 @@
+use super::Optimizer;
+
+use rand::RngCore;
+use serde::Deserialize;
+
+/// Walks a regular grid with `steps` points per axis, placed at the center of each cell.
+///
+/// Once every point has been handed out the walk starts over, so the grid is evaluated again
+/// with new simulation seeds
+pub struct GridSearch {
+    dimensions: usize,
+    steps: usize,
+    next: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    steps: usize,
+    /// Index of the next grid point to hand out
+    next: usize,
+}
+
+impl GridSearch {
+    pub fn new(dimensions: usize, steps: usize) -> Self {
+        assert!(steps > 0);
+        Self {
+            dimensions,
+            steps,
+            next: 0,
+        }
+    }
+
+    fn total_points(&self) -> usize {
+        self.steps.pow(self.dimensions as u32)
+    }
+
+    /// Returns the point at `index`, where the first axis changes the fastest
+    fn point_at(&self, mut index: usize) -> Vec<f64> {
+        let mut point = Vec::with_capacity(self.dimensions);
+        for _ in 0..self.dimensions {
+            let step = index % self.steps;
+            index /= self.steps;
+            point.push((step as f64 + 0.5) / self.steps as f64);
+        }
+        point
+    }
+}
+
+impl Optimizer for GridSearch {
+    fn ask(&mut self, _rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        let point = self.point_at(self.next);
+        self.next = (self.next + 1) % self.total_points();
+        Ok(point)
+    }
+
+    fn tell(&mut self, _point: &[f64], _error: f64) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(State {
+            steps: self.steps,
+            next: self.next,
+        })
+        .unwrap()
+    }
+
+    fn restore_state(&mut self, state: &serde_json::Value) -> Result<bool, crate::Error> {
+        let state = State::deserialize(state)?;
+        if state.steps != self.steps {
+            return Err(format!(
+                "Grid was saved with {} steps per axis but {} are in use",
+                state.steps, self.steps
+            )
+            .into());
+        }
+        self.next = state.next % self.total_points();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_every_cell() {
+        let mut grid = GridSearch::new(2, 2);
+        let mut rng = rand::thread_rng();
+        let points: Vec<Vec<f64>> = (0..5).map(|_| grid.ask(&mut rng).unwrap()).collect();
+        assert_eq!(
+            points,
+            vec![
+                vec![0.25, 0.25],
+                vec![0.75, 0.25],
+                vec![0.25, 0.75],
+                vec![0.75, 0.75],
+                vec![0.25, 0.25],
+            ]
+        );
+    }
+
+    #[test]
+    fn restores_position() {
+        let mut rng = rand::thread_rng();
+        let mut grid = GridSearch::new(2, 3);
+        for _ in 0..4 {
+            grid.ask(&mut rng).unwrap();
+        }
+
+        let mut restored = GridSearch::new(2, 3);
+        assert!(restored.restore_state(&grid.save_state()).unwrap());
+        assert_eq!(restored.ask(&mut rng).unwrap(), grid.ask(&mut rng).unwrap());
+
+        let mut finer = GridSearch::new(2, 4);
+        assert!(finer.restore_state(&grid.save_state()).is_err());
+    }
+}
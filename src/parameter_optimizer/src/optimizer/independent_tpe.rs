@@ -0,0 +1,161 @@
+use super::{liar_error, Optimizer, INITIAL_ERROR};
+
+use rand::RngCore;
+
+/// Tree-structured Parzen estimator with one independent estimator per parameter.
+///
+/// Every estimator is told the same error, so correlations between parameters are not modeled
+pub struct IndependentTpe {
+    estimators: Vec<tpe::TpeOptimizer>,
+    /// The errors of the real runs. The estimators also know the made up error of the initial
+    /// guess, and sort their trials so it can't be told apart
+    errors: Vec<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct State {
+    /// The `(value, error)` trials known to each estimator
+    trials: Vec<Vec<(f64, f64)>>,
+}
+
+impl IndependentTpe {
+    pub fn new(initial: &[f64]) -> Result<Self, crate::Error> {
+        let mut estimators = Vec::with_capacity(initial.len());
+        for value in initial {
+            let mut optim = estimator()?;
+            // Fill in a default value so the parameters start around the initial guess
+            optim.tell(clamp_to_range(*value), INITIAL_ERROR)?;
+            estimators.push(optim);
+        }
+        Ok(Self {
+            estimators,
+            errors: Vec::new(),
+        })
+    }
+
+    /// The error pending points are assumed to have. The made up error of the initial guess would
+    /// make it far worse than any real run
+    fn lie(&self) -> f64 {
+        liar_error(self.errors.iter().copied())
+    }
+}
+
+fn estimator() -> Result<tpe::TpeOptimizer, crate::Error> {
+    Ok(tpe::TpeOptimizer::new(
+        tpe::parzen_estimator(),
+        tpe::range(0.0, 1.0)?,
+    ))
+}
+
+/// `tpe::Range` excludes its end, so points on the upper edge of the hypercube must be pulled in
+fn clamp_to_range(value: f64) -> f64 {
+    value.clamp(0.0, 1.0 - f64::EPSILON)
+}
+
+impl Optimizer for IndependentTpe {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        let mut point = Vec::with_capacity(self.estimators.len());
+        for optim in self.estimators.iter_mut() {
+            point.push(optim.ask(rng)?);
+        }
+        Ok(point)
+    }
+
+    fn ask_pending(
+        &mut self,
+        rng: &mut dyn RngCore,
+        pending: &[Vec<f64>],
+    ) -> Result<Vec<f64>, crate::Error> {
+        if pending.is_empty() {
+            return self.ask(rng);
+        }
+        // The estimators can't forget a trial, so the lies are told to temporary copies
+        let lie = self.lie();
+        let mut point = Vec::with_capacity(self.estimators.len());
+        for (axis, optim) in self.estimators.iter().enumerate() {
+            let mut lying = estimator()?;
+            for (value, error) in optim.trials() {
+                lying.tell(value, error)?;
+            }
+            for pending in pending {
+                lying.tell(clamp_to_range(pending[axis]), lie)?;
+            }
+            point.push(lying.ask(rng)?);
+        }
+        Ok(point)
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        for (optim, value) in self.estimators.iter_mut().zip(point) {
+            optim.tell(clamp_to_range(*value), error)?;
+        }
+        self.errors.push(error);
+        Ok(())
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        let state = State {
+            trials: self
+                .estimators
+                .iter()
+                .map(|optim| optim.trials().collect())
+                .collect(),
+        };
+        serde_json::to_value(state).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Mean distance from each point in a batch to its nearest neighbour
+    fn spread(points: &[Vec<f64>]) -> f64 {
+        let nearest = |i: usize| {
+            (0..points.len())
+                .filter(|j| *j != i)
+                .map(|j| {
+                    points[i]
+                        .iter()
+                        .zip(points[j].iter())
+                        .map(|(a, b)| (a - b).powi(2))
+                        .sum::<f64>()
+                        .sqrt()
+                })
+                .fold(f64::INFINITY, f64::min)
+        };
+        (0..points.len()).map(nearest).sum::<f64>() / points.len() as f64
+    }
+
+    #[test]
+    fn pending_points_spread_batch() {
+        let error = |p: &[f64]| (p[0] - 0.3).powi(2) + (p[1] - 0.6).powi(2);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+        let mut optim = IndependentTpe::new(&[0.5, 0.5]).unwrap();
+        for _ in 0..60 {
+            let point = optim.ask(&mut rng).unwrap();
+            optim.tell(&point, error(&point)).unwrap();
+        }
+
+        // No real error is near the made up one of the initial guess
+        assert!(optim.lie() < 1.0, "{}", optim.lie());
+
+        // Eight runners ask before any of them is told
+        let mut naive = Vec::new();
+        let mut lying = Vec::new();
+        for _ in 0..8 {
+            naive.push(optim.ask(&mut rng).unwrap());
+            let point = optim.ask_pending(&mut rng, &lying).unwrap();
+            lying.push(point);
+        }
+        assert!(
+            spread(&lying) > 2.0 * spread(&naive),
+            "{} {}",
+            spread(&lying),
+            spread(&naive)
+        );
+        // The lies are forgotten again
+        assert_eq!(optim.estimators[0].trials().count(), 61);
+    }
+}
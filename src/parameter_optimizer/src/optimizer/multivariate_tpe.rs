@@ -0,0 +1,241 @@
+use super::{liar_error, Optimizer, INITIAL_ERROR};
+
+use rand::{Rng, RngCore};
+
+/// The fraction of observations that make up the "good" density
+const GAMMA: f64 = 0.15;
+
+/// How many candidates are drawn from the good density on each ask
+const CANDIDATES: usize = 24;
+
+/// Tree-structured Parzen estimator that models the whole parameter vector at once.
+///
+/// Each observation contributes one kernel over every dimension, so the good and bad densities
+/// are mixtures of multivariate Gaussians. Unlike [`super::IndependentTpe`], this keeps the
+/// relationship between parameters: if good runs lie along a diagonal, so do the candidates.
+pub struct MultivariateTpe {
+    dimensions: usize,
+    observations: Vec<Observation>,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct Observation {
+    point: Vec<f64>,
+    error: f64,
+    /// Whether this is the made up observation of the initial guess rather than a real run
+    #[serde(skip)]
+    seed: bool,
+}
+
+#[derive(serde::Serialize)]
+struct State<'a> {
+    observations: &'a [Observation],
+}
+
+/// A mixture of Gaussian kernels, one per observation plus a wide prior in the middle of the
+/// unit hypercube. All kernels share the same per-dimension bandwidth
+struct ParzenEstimator {
+    means: Vec<Vec<f64>>,
+    bandwidths: Vec<f64>,
+}
+
+impl ParzenEstimator {
+    fn new(points: &[&[f64]], dimensions: usize) -> Self {
+        let n = points.len();
+        let mut means: Vec<Vec<f64>> = points.iter().map(|p| p.to_vec()).collect();
+        means.push(vec![0.5; dimensions]);
+
+        // Scott's rule for multivariate kernels
+        let scale = (n.max(1) as f64).powf(-1.0 / (dimensions as f64 + 4.0));
+        let min_bandwidth = 1.0 / 100f64.min(1.0 + n as f64);
+        let bandwidths = (0..dimensions)
+            .map(|d| {
+                let stddev = if n < 2 {
+                    1.0
+                } else {
+                    let mean = points.iter().map(|p| p[d]).sum::<f64>() / n as f64;
+                    let variance =
+                        points.iter().map(|p| (p[d] - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+                    variance.sqrt()
+                };
+                (stddev * scale).clamp(min_bandwidth, 1.0)
+            })
+            .collect();
+
+        Self { means, bandwidths }
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> Vec<f64> {
+        let mean = &self.means[rng.gen_range(0..self.means.len())];
+        mean.iter()
+            .zip(self.bandwidths.iter())
+            .map(|(mean, bandwidth)| sample_truncated_normal(rng, *mean, *bandwidth))
+            .collect()
+    }
+
+    fn log_pdf(&self, x: &[f64]) -> f64 {
+        // Log-sum-exp over the kernels to avoid underflow far away from every observation
+        let log_kernels: Vec<f64> = self
+            .means
+            .iter()
+            .map(|mean| {
+                mean.iter()
+                    .zip(self.bandwidths.iter())
+                    .zip(x.iter())
+                    .map(|((mean, bandwidth), x)| log_normal_pdf(*x, *mean, *bandwidth))
+                    .sum::<f64>()
+            })
+            .collect();
+        let max = log_kernels
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let sum: f64 = log_kernels.iter().map(|l| (l - max).exp()).sum();
+        max + sum.ln() - (self.means.len() as f64).ln()
+    }
+}
+
+fn log_normal_pdf(x: f64, mean: f64, stddev: f64) -> f64 {
+    let z = (x - mean) / stddev;
+    -0.5 * z * z - stddev.ln() - 0.5 * (2.0 * std::f64::consts::PI).ln()
+}
+
+/// Samples a normal distribution restricted to `0.0..=1.0`
+fn sample_truncated_normal(rng: &mut dyn RngCore, mean: f64, stddev: f64) -> f64 {
+    for _ in 0..100 {
+        let z: f64 = rng.sample(rand_distr::StandardNormal);
+        let x = mean + z * stddev;
+        if (0.0..=1.0).contains(&x) {
+            return x;
+        }
+    }
+    // Almost all of the mass is outside the range, just pick the closest valid value
+    mean.clamp(0.0, 1.0)
+}
+
+impl MultivariateTpe {
+    pub fn new(initial: &[f64]) -> Self {
+        Self {
+            dimensions: initial.len(),
+            // Fill in a default value so the search starts around the initial guess
+            observations: vec![Observation {
+                point: initial.to_vec(),
+                error: INITIAL_ERROR,
+                seed: true,
+            }],
+        }
+    }
+
+    /// The error pending points are assumed to have. The made up error of the initial guess
+    /// would make it far worse than any real run
+    fn lie(&self) -> f64 {
+        liar_error(
+            self.observations
+                .iter()
+                .filter(|o| !o.seed)
+                .map(|o| o.error),
+        )
+    }
+}
+
+impl Optimizer for MultivariateTpe {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        self.observations
+            .sort_by(|a, b| a.error.partial_cmp(&b.error).unwrap());
+        let split = (self.observations.len() as f64 * GAMMA).ceil() as usize;
+        let (good, bad) = self.observations.split_at(split);
+
+        let good: Vec<&[f64]> = good.iter().map(|o| o.point.as_slice()).collect();
+        let bad: Vec<&[f64]> = bad.iter().map(|o| o.point.as_slice()).collect();
+        let good = ParzenEstimator::new(&good, self.dimensions);
+        let bad = ParzenEstimator::new(&bad, self.dimensions);
+
+        let mut best_score = f64::NEG_INFINITY;
+        let mut best = good.sample(rng);
+        for _ in 0..CANDIDATES {
+            let candidate = good.sample(rng);
+            let score = good.log_pdf(&candidate) - bad.log_pdf(&candidate);
+            if score > best_score {
+                best_score = score;
+                best = candidate;
+            }
+        }
+        Ok(best)
+    }
+
+    fn ask_pending(
+        &mut self,
+        rng: &mut dyn RngCore,
+        pending: &[Vec<f64>],
+    ) -> Result<Vec<f64>, crate::Error> {
+        let lie = self.lie();
+        let observations = self.observations.clone();
+        self.observations
+            .extend(pending.iter().map(|point| Observation {
+                point: point.clone(),
+                error: lie,
+                seed: false,
+            }));
+        let point = self.ask(rng);
+        self.observations = observations;
+        point
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        if error.is_nan() {
+            return Err("Cannot tell a NaN error to the optimizer".into());
+        }
+        self.observations.push(Observation {
+            point: point.to_vec(),
+            error,
+            seed: false,
+        });
+        Ok(())
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(State {
+            observations: &self.observations,
+        })
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn follows_diagonal_valley() {
+        // The minimum lies anywhere on the line x == y, with a slight preference for 0.7
+        let error = |p: &[f64]| 100.0 * (p[0] - p[1]).powi(2) + (p[0] - 0.7).powi(2);
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut optim = MultivariateTpe::new(&[0.1, 0.1]);
+        for _ in 0..150 {
+            let point = optim.ask(&mut rng).unwrap();
+            assert!(point.iter().all(|x| (0.0..=1.0).contains(x)));
+            optim.tell(&point, error(&point)).unwrap();
+        }
+        // No real error is near the made up one of the initial guess
+        assert!(optim.lie() < 101.0, "{}", optim.lie());
+
+        // The last suggestions should sit close to the valley floor
+        let recent: Vec<Vec<f64>> = (0..20).map(|_| optim.ask(&mut rng).unwrap()).collect();
+        let mean_offset =
+            recent.iter().map(|p| (p[0] - p[1]).abs()).sum::<f64>() / recent.len() as f64;
+        assert!(
+            mean_offset < 0.1,
+            "mean offset from diagonal: {}",
+            mean_offset
+        );
+
+        let best = optim
+            .observations
+            .iter()
+            .map(|o| o.error)
+            .fold(f64::INFINITY, f64::min);
+        assert!(best < 0.05, "best error: {}", best);
+    }
+}
@@ -0,0 +1,283 @@
+use super::Optimizer;
+use crate::pareto;
+
+use rand::{Rng, RngCore};
+use serde::Deserialize;
+
+/// Distribution index of the simulated binary crossover. Larger values keep children closer to
+/// their parents
+const CROSSOVER_ETA: f64 = 15.0;
+
+/// Distribution index of the polynomial mutation
+const MUTATION_ETA: f64 = 20.0;
+
+/// Steady state NSGA-II which keeps every objective separate instead of minimizing their sum.
+///
+/// Until the population is full, points are sampled at random. After that every ask breeds one
+/// child from two tournament winners, and every tell inserts the result and drops the member
+/// with the worst rank and crowding distance
+pub struct Nsga2 {
+    dimensions: usize,
+    population_size: usize,
+    population: Vec<Member>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Member {
+    point: Vec<f64>,
+    objectives: Vec<f64>,
+    /// Index of the front this member is in. Zero is the Pareto front
+    #[serde(skip)]
+    rank: usize,
+    #[serde(skip)]
+    crowding: f64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    population: Vec<Member>,
+}
+
+impl Nsga2 {
+    pub fn new(dimensions: usize, population_size: usize) -> Self {
+        Self {
+            dimensions,
+            // Tournaments need a few members to choose from
+            population_size: population_size.max(4),
+            population: Vec::new(),
+        }
+    }
+
+    /// Updates the rank and crowding distance of every member
+    fn assign_fitness(&mut self) {
+        let objectives: Vec<Vec<f64>> = self
+            .population
+            .iter()
+            .map(|m| m.objectives.clone())
+            .collect();
+        for (rank, front) in pareto::non_dominated_sort(&objectives)
+            .into_iter()
+            .enumerate()
+        {
+            let distances = pareto::crowding_distance(&objectives, &front);
+            for (index, distance) in front.into_iter().zip(distances) {
+                self.population[index].rank = rank;
+                self.population[index].crowding = distance;
+            }
+        }
+    }
+
+    /// Removes the worst members until the population fits
+    fn truncate(&mut self) {
+        while self.population.len() > self.population_size {
+            self.assign_fitness();
+            let worst = (0..self.population.len())
+                .max_by(|a, b| {
+                    let (a, b) = (&self.population[*a], &self.population[*b]);
+                    a.rank
+                        .cmp(&b.rank)
+                        .then(b.crowding.partial_cmp(&a.crowding).unwrap())
+                })
+                .unwrap();
+            self.population.swap_remove(worst);
+        }
+        self.assign_fitness();
+    }
+
+    /// Binary tournament on rank, then on crowding distance
+    fn select(&self, rng: &mut dyn RngCore) -> &Member {
+        let a = &self.population[rng.gen_range(0..self.population.len())];
+        let b = &self.population[rng.gen_range(0..self.population.len())];
+        if a.rank < b.rank || (a.rank == b.rank && a.crowding > b.crowding) {
+            a
+        } else {
+            b
+        }
+    }
+}
+
+/// Simulated binary crossover of a single coordinate, restricted to the unit interval
+fn crossover(rng: &mut dyn RngCore, a: f64, b: f64) -> f64 {
+    let u: f64 = rng.gen();
+    let beta = if u <= 0.5 {
+        (2.0 * u).powf(1.0 / (CROSSOVER_ETA + 1.0))
+    } else {
+        (1.0 / (2.0 * (1.0 - u))).powf(1.0 / (CROSSOVER_ETA + 1.0))
+    };
+    let child = if rng.gen() {
+        0.5 * ((1.0 + beta) * a + (1.0 - beta) * b)
+    } else {
+        0.5 * ((1.0 - beta) * a + (1.0 + beta) * b)
+    };
+    child.clamp(0.0, 1.0)
+}
+
+/// Polynomial mutation of a single coordinate, restricted to the unit interval
+fn mutate(rng: &mut dyn RngCore, x: f64) -> f64 {
+    let u: f64 = rng.gen();
+    let delta = if u < 0.5 {
+        (2.0 * u).powf(1.0 / (MUTATION_ETA + 1.0)) - 1.0
+    } else {
+        1.0 - (2.0 * (1.0 - u)).powf(1.0 / (MUTATION_ETA + 1.0))
+    };
+    (x + delta).clamp(0.0, 1.0)
+}
+
+impl Optimizer for Nsga2 {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        if self.population.len() < self.population_size {
+            return Ok((0..self.dimensions)
+                .map(|_| rng.gen_range(0.0..=1.0))
+                .collect());
+        }
+        let mother = self.select(rng).point.clone();
+        let father = self.select(rng).point.clone();
+        let mutation_probability = 1.0 / self.dimensions as f64;
+        Ok(mother
+            .iter()
+            .zip(father.iter())
+            .map(|(a, b)| {
+                let child = crossover(rng, *a, *b);
+                if rng.gen_bool(mutation_probability) {
+                    mutate(rng, child)
+                } else {
+                    child
+                }
+            })
+            .collect())
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        self.tell_objectives(point, error, &[])
+    }
+
+    fn tell_objectives(
+        &mut self,
+        point: &[f64],
+        _error: f64,
+        objectives: &[f64],
+    ) -> Result<(), crate::Error> {
+        if objectives.is_empty() {
+            return Err("NSGA-II needs the value of every objective".into());
+        }
+        if objectives.iter().any(|o| o.is_nan()) {
+            return Err("Cannot tell a NaN objective to the optimizer".into());
+        }
+        if let Some(member) = self.population.first() {
+            if member.objectives.len() != objectives.len() {
+                return Err(format!(
+                    "Expected {} objectives but got {}",
+                    member.objectives.len(),
+                    objectives.len()
+                )
+                .into());
+            }
+        }
+        self.population.push(Member {
+            point: point.to_vec(),
+            objectives: objectives.to_vec(),
+            rank: 0,
+            crowding: 0.0,
+        });
+        self.truncate();
+        Ok(())
+    }
+
+    /// The point is made worse than every member in each objective, so it is always in the last
+    /// front. With no members to compare against it is forgotten
+    fn tell_infeasible(&mut self, point: &[f64], _penalty: f64) -> Result<(), crate::Error> {
+        let objectives = match self.population.first() {
+            Some(member) => member.objectives.len(),
+            None => return Ok(()),
+        };
+        let worst: Vec<f64> = (0..objectives)
+            .map(|i| {
+                let worst = self
+                    .population
+                    .iter()
+                    .map(|m| m.objectives[i])
+                    .fold(f64::NEG_INFINITY, f64::max);
+                worst + worst.abs().max(1.0) * 0.1
+            })
+            .collect();
+        self.tell_objectives(point, f64::NAN, &worst)
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(State {
+            population: self.population.clone(),
+        })
+        .unwrap()
+    }
+
+    fn restore_state(&mut self, state: &serde_json::Value) -> Result<bool, crate::Error> {
+        let state = State::deserialize(state)?;
+        if state
+            .population
+            .iter()
+            .any(|m| m.point.len() != self.dimensions)
+        {
+            return Err(format!(
+                "Saved population does not have {} dimensions",
+                self.dimensions
+            )
+            .into());
+        }
+        self.population = state.population;
+        self.truncate();
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn spreads_along_front() {
+        // Schaffer's problem: the Pareto set is x in [0.4, 0.6], a trade off between the two
+        // objectives
+        let objectives = |p: &[f64]| vec![(p[0] - 0.4).powi(2), (p[0] - 0.6).powi(2)];
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut optim = Nsga2::new(1, 20);
+        for _ in 0..400 {
+            let point = optim.ask(&mut rng).unwrap();
+            let values = objectives(&point);
+            optim
+                .tell_objectives(&point, values.iter().sum(), &values)
+                .unwrap();
+        }
+
+        assert_eq!(optim.population.len(), 20);
+        assert!(optim.population.iter().all(|m| m.rank == 0));
+        let xs: Vec<f64> = optim.population.iter().map(|m| m.point[0]).collect();
+        assert!(xs.iter().all(|x| (0.39..=0.61).contains(x)), "{:?}", xs);
+        // The population should cover the whole front rather than collapse on one point
+        let min = xs.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = xs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert!(max - min > 0.15, "{:?}", xs);
+    }
+
+    #[test]
+    fn rejects_missing_objectives() {
+        let mut optim = Nsga2::new(2, 8);
+        assert!(optim.tell(&[0.5, 0.5], 1.0).is_err());
+        optim
+            .tell_objectives(&[0.5, 0.5], 3.0, &[1.0, 2.0])
+            .unwrap();
+        assert!(optim
+            .tell_objectives(&[0.5, 0.5], 3.0, &[1.0, 2.0, 3.0])
+            .is_err());
+
+        optim.tell_infeasible(&[0.1, 0.1], 0.0).unwrap();
+        let infeasible = optim
+            .population
+            .iter()
+            .find(|m| m.point == [0.1, 0.1])
+            .unwrap();
+        assert!(infeasible.rank > 0);
+        assert!(infeasible.objectives[0] > 1.0 && infeasible.objectives[1] > 2.0);
+    }
+}
@@ -0,0 +1,30 @@
+use super::Optimizer;
+
+use rand::{Rng, RngCore};
+
+/// Samples every parameter uniformly at random. Useful as a baseline for the other backends
+pub struct RandomSearch {
+    dimensions: usize,
+}
+
+impl RandomSearch {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions }
+    }
+}
+
+impl Optimizer for RandomSearch {
+    fn ask(&mut self, rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        Ok((0..self.dimensions)
+            .map(|_| rng.gen_range(0.0..=1.0))
+            .collect())
+    }
+
+    fn tell(&mut self, _point: &[f64], _error: f64) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+}
@@ -0,0 +1,193 @@
+use super::Optimizer;
+use crate::sensitivity::{Design, Evaluation};
+
+use rand::RngCore;
+use serde::Deserialize;
+
+/// Not an optimizer: hands out the points of a sensitivity analysis design and stores the results
+/// so that the indices can be computed from the saved state.
+///
+/// Once every point has been handed out, points whose simulation failed or has not finished yet
+/// are handed out again until the design is complete. A point that is infeasible
+/// [`MAX_INFEASIBLE`] times ends the analysis, since the indices can't be computed without it
+pub struct SensitivityDesign {
+    design: Design,
+    next: usize,
+    /// How often each point turned out infeasible
+    infeasible: Vec<usize>,
+}
+
+/// How often a point may turn out infeasible before the design is given up on
+pub const MAX_INFEASIBLE: usize = 3;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct State {
+    design: Design,
+    next: usize,
+    #[serde(default)]
+    infeasible: Vec<usize>,
+}
+
+impl SensitivityDesign {
+    pub fn new(design: Design) -> Self {
+        let infeasible = vec![0; design.points.len()];
+        Self {
+            design,
+            next: 0,
+            infeasible,
+        }
+    }
+
+    /// The first point without a result that equals `point`. Points are handed back unchanged,
+    /// so they can be matched exactly
+    fn find(&self, point: &[f64]) -> Option<usize> {
+        (0..self.design.points.len())
+            .find(|i| self.design.evaluations[*i].is_none() && self.design.points[*i] == point)
+    }
+}
+
+impl Optimizer for SensitivityDesign {
+    fn ask(&mut self, _rng: &mut dyn RngCore) -> Result<Vec<f64>, crate::Error> {
+        let len = self.design.points.len();
+        // Cycle through the points that still need a result
+        let index = (0..len)
+            .map(|offset| (self.next + offset) % len)
+            .find(|i| self.design.evaluations[*i].is_none())
+            .unwrap_or(self.next % len);
+        if self.infeasible[index] >= MAX_INFEASIBLE {
+            return Err(format!(
+                "Point {} of the sensitivity design {:?} was infeasible {} times, so the indices \
+                 can't be computed",
+                index, self.design.points[index], self.infeasible[index]
+            )
+            .into());
+        }
+        self.next = index + 1;
+        Ok(self.design.points[index].clone())
+    }
+
+    fn tell(&mut self, point: &[f64], error: f64) -> Result<(), crate::Error> {
+        self.tell_objectives(point, error, &[])
+    }
+
+    fn tell_objectives(
+        &mut self,
+        point: &[f64],
+        error: f64,
+        objectives: &[f64],
+    ) -> Result<(), crate::Error> {
+        if let Some(index) = self.find(point) {
+            self.design.evaluations[index] = Some(Evaluation {
+                error,
+                objectives: objectives.to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The point is left without a result so that it is handed out again. The penalty is not a
+    /// real output, so it must not end up in the indices
+    fn tell_infeasible(&mut self, point: &[f64], _penalty: f64) -> Result<(), crate::Error> {
+        if let Some(index) = self.find(point) {
+            self.infeasible[index] += 1;
+        }
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.design.is_complete()
+    }
+
+    fn save_state(&self) -> serde_json::Value {
+        serde_json::to_value(State {
+            design: self.design.clone(),
+            next: self.next,
+            infeasible: self.infeasible.clone(),
+        })
+        .unwrap()
+    }
+
+    fn restore_state(&mut self, state: &serde_json::Value) -> Result<bool, crate::Error> {
+        let state = State::deserialize(state)?;
+        if state.design.dimensions != self.design.dimensions {
+            return Err(format!(
+                "Saved design does not have {} dimensions",
+                self.design.dimensions
+            )
+            .into());
+        }
+        self.infeasible = state.infeasible;
+        self.infeasible.resize(state.design.points.len(), 0);
+        self.design = state.design;
+        self.next = state.next;
+        Ok(true)
+    }
+}
+
+/// Reads the design back from the saved state of a [`SensitivityDesign`]
+pub fn saved_design(state: &serde_json::Value) -> Result<Design, crate::Error> {
+    Ok(State::deserialize(state)?.design)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn retries_missing_points() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut optim = SensitivityDesign::new(Design::morris(2, 2, &mut rng));
+        let points: Vec<Vec<f64>> = (0..6).map(|_| optim.ask(&mut rng).unwrap()).collect();
+        for (i, point) in points.iter().enumerate() {
+            // The third point fails
+            if i != 2 {
+                optim.tell(point, i as f64).unwrap();
+            }
+        }
+        assert!(!optim.is_finished());
+        let retry = optim.ask(&mut rng).unwrap();
+        assert_eq!(retry, points[2]);
+        optim.tell(&retry, 2.0).unwrap();
+        assert!(optim.is_finished());
+
+        let design = saved_design(&optim.save_state()).unwrap();
+        let errors: Vec<f64> = design
+            .evaluations
+            .iter()
+            .map(|e| e.as_ref().unwrap().error)
+            .collect();
+        assert_eq!(errors, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn retries_infeasible_points() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let mut optim = SensitivityDesign::new(Design::morris(2, 2, &mut rng));
+        let points: Vec<Vec<f64>> = (0..6).map(|_| optim.ask(&mut rng).unwrap()).collect();
+        for (i, point) in points.iter().enumerate() {
+            if i == 4 {
+                optim.tell_infeasible(point, 1e6).unwrap();
+            } else {
+                optim.tell(point, i as f64).unwrap();
+            }
+        }
+        assert!(!optim.is_finished());
+        let design = saved_design(&optim.save_state()).unwrap();
+        assert!(design.evaluations[4].is_none());
+
+        // Asked again until it has been infeasible too often
+        for _ in 1..MAX_INFEASIBLE {
+            let retry = optim.ask(&mut rng).unwrap();
+            assert_eq!(retry, points[4]);
+            optim.tell_infeasible(&retry, 1e6).unwrap();
+        }
+        let err = optim.ask(&mut rng).unwrap_err().to_string();
+        assert!(err.contains("infeasible 3 times"), "{}", err);
+
+        // The count survives a restart
+        let mut restored = SensitivityDesign::new(Design::morris(2, 2, &mut rng));
+        assert!(restored.restore_state(&optim.save_state()).unwrap());
+        assert!(restored.ask(&mut rng).is_err());
+    }
+}
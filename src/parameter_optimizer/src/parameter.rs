@@ -0,0 +1,357 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Bounds used by runs exported before each parameter declared its own range
+const LEGACY_MIN: f64 = 0.0;
+const LEGACY_MAX: f64 = 18.0;
+
+/// How values are spread between `min` and `max`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scale {
+    #[default]
+    Linear,
+    /// Equal ratios are treated as equal distances. Useful for intervals spanning several
+    /// orders of magnitude
+    Log,
+}
+
+/// The kind of values a parameter can take
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    #[default]
+    Float,
+    /// Whole numbers between `min` and `max` inclusive
+    Integer,
+    /// One of a fixed set of values, which have no meaningful order
+    Categorical(Vec<f64>),
+}
+
+/// A value passed to the simulation as `--<name>=<value>` that is chosen by the optimizer
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Parameter {
+    pub name: String,
+    #[serde(default = "legacy_min")]
+    pub min: f64,
+    #[serde(default = "legacy_max")]
+    pub max: f64,
+    #[serde(default)]
+    pub scale: Scale,
+    #[serde(default, rename = "type")]
+    pub kind: ParameterType,
+    /// The value to start the search around, if any
+    #[serde(default)]
+    pub default: Option<f64>,
+}
+
+// Serde needs functions for default values
+fn legacy_min() -> f64 {
+    LEGACY_MIN
+}
+
+fn legacy_max() -> f64 {
+    LEGACY_MAX
+}
+
+impl Parameter {
+    /// Creates a floating point parameter with a linear scale
+    pub fn float(name: &str, min: f64, max: f64) -> Self {
+        Self {
+            name: name.to_owned(),
+            min,
+            max,
+            scale: Scale::Linear,
+            kind: ParameterType::Float,
+            default: None,
+        }
+    }
+
+    /// Creates a parameter with the range every parameter shared before they declared their own
+    pub fn legacy(name: &str) -> Self {
+        Self::float(name, LEGACY_MIN, LEGACY_MAX)
+    }
+
+    pub fn with_default(mut self, default: f64) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    /// Applies the scale of this parameter
+    fn transform(&self, value: f64) -> f64 {
+        match self.scale {
+            Scale::Linear => value,
+            Scale::Log => value.ln(),
+        }
+    }
+
+    fn inverse_transform(&self, value: f64) -> f64 {
+        match self.scale {
+            Scale::Linear => value,
+            Scale::Log => value.exp(),
+        }
+    }
+
+    /// Returns the range covered by the unit interval after the scale is applied. Integers get
+    /// an extra half step on each side so that every whole number is equally likely
+    fn transformed_bounds(&self) -> (f64, f64) {
+        match self.kind {
+            ParameterType::Integer => (
+                self.transform(self.min - 0.5),
+                self.transform(self.max + 0.5),
+            ),
+            _ => (self.transform(self.min), self.transform(self.max)),
+        }
+    }
+
+    /// Maps a coordinate from the optimizer's unit hypercube onto a value of this parameter
+    pub fn denormalize(&self, unit: f64) -> f64 {
+        let unit = unit.clamp(0.0, 1.0);
+        match &self.kind {
+            ParameterType::Categorical(choices) => {
+                let index = ((unit * choices.len() as f64) as usize).min(choices.len() - 1);
+                choices[index]
+            }
+            ParameterType::Float => {
+                let (low, high) = self.transformed_bounds();
+                let value = self.inverse_transform(crate::util::map(0.0, 1.0, unit, low, high));
+                value.clamp(self.min, self.max)
+            }
+            ParameterType::Integer => {
+                let (low, high) = self.transformed_bounds();
+                let value = self.inverse_transform(crate::util::map(0.0, 1.0, unit, low, high));
+                value.round().clamp(self.min, self.max)
+            }
+        }
+    }
+
+    /// Maps a value of this parameter into the optimizer's unit hypercube
+    pub fn normalize(&self, value: f64) -> f64 {
+        match &self.kind {
+            ParameterType::Categorical(choices) => {
+                let index = choices
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| {
+                        (*a - value).abs().partial_cmp(&(*b - value).abs()).unwrap()
+                    })
+                    .map(|(i, _)| i)
+                    .unwrap();
+                (index as f64 + 0.5) / choices.len() as f64
+            }
+            _ => {
+                let (low, high) = self.transformed_bounds();
+                let value = value.clamp(self.min, self.max);
+                crate::util::map(low, high, self.transform(value), 0.0, 1.0)
+            }
+        }
+    }
+
+    /// The point in the unit interval to start the search around
+    pub fn initial(&self) -> f64 {
+        match self.default {
+            Some(default) => self.normalize(default),
+            None => 0.5,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("parameter name is empty".to_owned());
+        }
+        if let ParameterType::Categorical(choices) = &self.kind {
+            if choices.is_empty() {
+                return Err(format!("{} has no choices", self.name));
+            }
+            return Ok(());
+        }
+        if self.min.partial_cmp(&self.max) != Some(Ordering::Less) {
+            return Err(format!(
+                "{} has an empty range: {}..{}",
+                self.name, self.min, self.max
+            ));
+        }
+        if self.scale == Scale::Log && self.min <= 0.0 {
+            return Err(format!(
+                "{} uses a log scale so its minimum must be positive",
+                self.name
+            ));
+        }
+        if self.kind == ParameterType::Integer
+            && (self.min.fract() != 0.0 || self.max.fract() != 0.0)
+        {
+            return Err(format!(
+                "{} is an integer so its bounds must be whole",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}=", self.name)?;
+        match &self.kind {
+            ParameterType::Categorical(choices) => {
+                let choices: Vec<String> = choices.iter().map(|c| c.to_string()).collect();
+                write!(f, "{}", choices.join("|"))?;
+            }
+            ParameterType::Float => write!(f, "{}..{}", self.min, self.max)?,
+            ParameterType::Integer => write!(f, "{}..{},int", self.min, self.max)?,
+        }
+        if self.scale == Scale::Log {
+            write!(f, ",log")?;
+        }
+        if let Some(default) = self.default {
+            write!(f, ",default={}", default)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a parameter in the format used on the command line:
+///
+/// `<name>=<min>..<max>[,log][,int][,default=<value>]` or `<name>=<choice>|<choice>|...`
+impl FromStr for Parameter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <name>=<range> but got `{}`", s))?;
+        let mut parts = spec.split(',');
+        let range = parts.next().unwrap_or("");
+
+        let parse_number = |value: &str| -> Result<f64, String> {
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|err| format!("invalid number `{}` in `{}`: {}", value, s, err))
+        };
+
+        let mut param = if let Some((min, max)) = range.split_once("..") {
+            Parameter::float(name, parse_number(min)?, parse_number(max)?)
+        } else {
+            let choices = range
+                .split('|')
+                .map(parse_number)
+                .collect::<Result<Vec<f64>, String>>()?;
+            let min = choices.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = choices.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            Parameter {
+                kind: ParameterType::Categorical(choices),
+                ..Parameter::float(name, min, max)
+            }
+        };
+
+        for option in parts {
+            match option.trim() {
+                // Choices are picked from a list, so there is no scale or rounding to change
+                option @ ("log" | "int") if matches!(param.kind, ParameterType::Categorical(_)) => {
+                    return Err(format!(
+                        "{} has a list of choices so `{}` does not apply in `{}`",
+                        param.name, option, s
+                    ))
+                }
+                "log" => param.scale = Scale::Log,
+                "int" if param.kind == ParameterType::Float => param.kind = ParameterType::Integer,
+                option => match option.split_once('=') {
+                    Some(("default", value)) => param.default = Some(parse_number(value)?),
+                    _ => return Err(format!("unknown option `{}` in `{}`", option, s)),
+                },
+            }
+        }
+
+        param.validate()?;
+        Ok(param)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let param: Parameter = "a=0..18,default=1".parse().unwrap();
+        assert_eq!(param, Parameter::float("a", 0.0, 18.0).with_default(1.0));
+
+        let param: Parameter = "packetInterval=0.01..10,log".parse().unwrap();
+        assert_eq!(param.scale, Scale::Log);
+        assert_eq!(param.kind, ParameterType::Float);
+
+        let param: Parameter = "pNodes=4..12,int".parse().unwrap();
+        assert_eq!(param.kind, ParameterType::Integer);
+
+        let param: Parameter = "calculateInterval=0.01|0.05|0.1".parse().unwrap();
+        assert_eq!(
+            param.kind,
+            ParameterType::Categorical(vec![0.01, 0.05, 0.1])
+        );
+        assert_eq!((param.min, param.max), (0.01, 0.1));
+
+        assert!("a".parse::<Parameter>().is_err());
+        assert!("a=5..1".parse::<Parameter>().is_err());
+        assert!("a=0..1,log".parse::<Parameter>().is_err());
+        assert!("a=0.5..4,int".parse::<Parameter>().is_err());
+        assert!("a=0..1,fast".parse::<Parameter>().is_err());
+        assert!("a=1|2|4,log".parse::<Parameter>().is_err());
+        assert!("a=1|2|4,int".parse::<Parameter>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for spec in &[
+            "a=0..18,default=1",
+            "packetInterval=0.01..10,log",
+            "pNodes=4..12,int",
+            "calculateInterval=0.01|0.05|0.1",
+        ] {
+            let param: Parameter = spec.parse().unwrap();
+            assert_eq!(param.to_string(), *spec);
+        }
+    }
+
+    #[test]
+    fn linear_and_log_mapping() {
+        let linear = Parameter::float("a", 0.0, 18.0);
+        assert_eq!(linear.denormalize(0.5), 9.0);
+        assert_eq!(linear.normalize(4.5), 0.25);
+
+        let log: Parameter = "b=0.01..100,log".parse().unwrap();
+        assert!((log.denormalize(0.5) - 1.0).abs() < 1e-9);
+        assert!((log.normalize(0.1) - 0.25).abs() < 1e-9);
+        assert!((log.denormalize(log.normalize(3.7)) - 3.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn integer_mapping() {
+        let param: Parameter = "pNodes=4..7,int".parse().unwrap();
+        let values: Vec<f64> = [0.0, 0.2, 0.3, 0.6, 0.8, 1.0]
+            .iter()
+            .map(|u| param.denormalize(*u))
+            .collect();
+        assert_eq!(values, vec![4.0, 4.0, 5.0, 6.0, 7.0, 7.0]);
+        for value in 4..=7 {
+            let value = value as f64;
+            assert_eq!(param.denormalize(param.normalize(value)), value);
+        }
+    }
+
+    #[test]
+    fn categorical_mapping() {
+        let param: Parameter = "c=0.1|0.5|0.2".parse().unwrap();
+        assert_eq!(param.denormalize(0.0), 0.1);
+        assert_eq!(param.denormalize(0.5), 0.5);
+        assert_eq!(param.denormalize(1.0), 0.2);
+        assert_eq!(param.normalize(0.2), 2.5 / 3.0);
+    }
+
+    #[test]
+    fn legacy_json() {
+        let param: Parameter = serde_json::from_str(r#"{"name":"a"}"#).unwrap();
+        assert_eq!(param, Parameter::legacy("a"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Helpers for comparing points with several objectives, all of which are minimized
+
+/// Returns true if `a` is at least as good as `b` in every objective and better in at least one
+pub fn dominates(a: &[f64], b: &[f64]) -> bool {
+    let mut better = false;
+    for (a, b) in a.iter().zip(b.iter()) {
+        if a > b {
+            return false;
+        }
+        if a < b {
+            better = true;
+        }
+    }
+    better
+}
+
+/// Sorts points into fronts. The first front contains the indices of the points that no other
+/// point dominates, the second front the points only dominated by the first front, and so on
+pub fn non_dominated_sort(points: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    // How many points dominate each point, and which points each point dominates
+    let mut dominated_by = vec![0usize; points.len()];
+    let mut dominating: Vec<Vec<usize>> = vec![Vec::new(); points.len()];
+    for i in 0..points.len() {
+        for j in (i + 1)..points.len() {
+            if dominates(&points[i], &points[j]) {
+                dominating[i].push(j);
+                dominated_by[j] += 1;
+            } else if dominates(&points[j], &points[i]) {
+                dominating[j].push(i);
+                dominated_by[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::new();
+    let mut current: Vec<usize> = (0..points.len())
+        .filter(|i| dominated_by[*i] == 0)
+        .collect();
+    while !current.is_empty() {
+        let mut next = Vec::new();
+        for i in &current {
+            for j in &dominating[*i] {
+                dominated_by[*j] -= 1;
+                if dominated_by[*j] == 0 {
+                    next.push(*j);
+                }
+            }
+        }
+        fronts.push(current);
+        current = next;
+    }
+    fronts
+}
+
+/// Returns the indices of the points which no other point dominates
+pub fn pareto_front(points: &[Vec<f64>]) -> Vec<usize> {
+    let mut front = non_dominated_sort(points)
+        .into_iter()
+        .next()
+        .unwrap_or_default();
+    front.sort_unstable();
+    front
+}
+
+/// Computes the crowding distance of each point in `front`, which are indices into `points`.
+/// Points at the edges of the front get an infinite distance so they are always kept
+pub fn crowding_distance(points: &[Vec<f64>], front: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    if front.len() <= 2 {
+        return vec![f64::INFINITY; front.len()];
+    }
+    // Value of an objective for the i-th point of the front
+    let value = |i: usize, objective: usize| points[front[i]][objective];
+    for objective in 0..points[front[0]].len() {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|a, b| {
+            value(*a, objective)
+                .partial_cmp(&value(*b, objective))
+                .unwrap()
+        });
+        let min = value(order[0], objective);
+        let max = value(order[order.len() - 1], objective);
+        distances[order[0]] = f64::INFINITY;
+        distances[order[order.len() - 1]] = f64::INFINITY;
+        if max - min <= 0.0 {
+            continue;
+        }
+        for window in order.windows(3) {
+            let before = value(window[0], objective);
+            let after = value(window[2], objective);
+            distances[window[1]] += (after - before) / (max - min);
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domination() {
+        assert!(dominates(&[1.0, 2.0], &[1.0, 3.0]));
+        assert!(dominates(&[0.0, 0.0], &[1.0, 1.0]));
+        assert!(!dominates(&[1.0, 2.0], &[1.0, 2.0]));
+        assert!(!dominates(&[1.0, 3.0], &[2.0, 2.0]));
+    }
+
+    #[test]
+    fn sorts_into_fronts() {
+        let points = vec![
+            vec![1.0, 4.0],
+            vec![2.0, 2.0],
+            vec![4.0, 1.0],
+            vec![3.0, 3.0],
+            vec![5.0, 5.0],
+            vec![2.0, 5.0],
+        ];
+        let fronts = non_dominated_sort(&points);
+        assert_eq!(fronts, vec![vec![0, 1, 2], vec![3, 5], vec![4]]);
+        assert_eq!(pareto_front(&points), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn crowding() {
+        let points = vec![
+            vec![0.0, 4.0],
+            vec![1.0, 3.0],
+            vec![3.0, 1.0],
+            vec![4.0, 0.0],
+        ];
+        let distances = crowding_distance(&points, &[0, 1, 2, 3]);
+        assert_eq!(distances[0], f64::INFINITY);
+        assert_eq!(distances[3], f64::INFINITY);
+        // Both middle points have the same neighbours in each objective
+        assert!((distances[1] - 1.5).abs() < 1e-9);
+        assert!((distances[2] - 1.5).abs() < 1e-9);
+    }
+}
@@ -120,20 +120,7 @@ impl SimulationData {
             frames.push(TimedObject::new(last_time, std::mem::take(&mut inner_map)));
         }
 
-        //Set the initial state. Because we assume the user starts the simulation at t=0, all the UAV's
-        //will be in the before state because we only know their position in the future
-        let mut state = HashMap::new();
-        if !frames.is_empty() {
-            for uav in &unique_ids {
-                for (i, _) in frames.iter().enumerate() {
-                    let entry = &frames[i];
-                    if entry.inner.contains_key(uav) {
-                        state.insert(*uav, InterpolationState::Before(i));
-                        break;
-                    }
-                }
-            }
-        }
+        let state = initial_state(&frames, &unique_ids);
         let simulation_length = frames[frames.len() - 1].time.0;
         Ok(Self {
             frames,
@@ -145,6 +132,13 @@ impl SimulationData {
         })
     }
 
+    /// Goes back to the start of the simulation, so that `pos_at_time` can be called from t=0
+    /// again by the next analysis
+    pub fn rewind(&mut self) {
+        self.state = initial_state(&self.frames, &self.uavs);
+        self.last_time = None;
+    }
+
     /// Returns the position of the specified UAV at the given point in time
     ///
     /// time must never decrease from one call of this function to the next
@@ -216,6 +210,24 @@ impl SimulationData {
     }
 }
 
+/// Because we assume the user starts the simulation at t=0, all the UAV's start in the before
+/// state because we only know their position in the future
+fn initial_state(
+    frames: &[TimedObject<HashMap<UavId, UavKeyFrame>>],
+    uavs: &HashSet<UavId>,
+) -> HashMap<UavId, InterpolationState> {
+    let mut state = HashMap::new();
+    for uav in uavs {
+        if let Some(i) = frames
+            .iter()
+            .position(|entry| entry.inner.contains_key(uav))
+        {
+            state.insert(*uav, InterpolationState::Before(i));
+        }
+    }
+    state
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -402,5 +414,15 @@ color,0,10.1.1.1,0.3,0.7,1,
             data.pos_at_time(TimePoint(67.0), uav).unwrap(),
             Vec3::new(0.0, 0.0, 0.0)
         );
+
+        data.rewind();
+        assert_approx_eq!(
+            data.pos_at_time(TimePoint(0.0), uav).unwrap(),
+            Vec3::new(0.0, 0.0, 0.0)
+        );
+        assert_approx_eq!(
+            data.pos_at_time(TimePoint(0.1), uav).unwrap(),
+            Vec3::new(1.0, 1.0, 1.0)
+        );
     }
 }
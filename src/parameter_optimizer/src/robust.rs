@@ -0,0 +1,207 @@
+//! Scoring parameters by how the swarm holds up across a set of attack scenarios, so that the
+//! optimizer favours parameters that stay stable under attack over ones that only work when
+//! nothing goes wrong
+
+use crate::warm_start::ScenarioArgument;
+
+use indexmap::IndexMap;
+use std::str::FromStr;
+
+/// How the errors of the scenarios are combined, as chosen on the command line
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ArgEnum)]
+pub enum RobustMode {
+    WorstCase,
+    Cvar,
+}
+
+impl RobustMode {
+    /// `cvar_fraction` is only used in CVaR mode, and must be above 0 and at most 1
+    pub fn aggregation(self, cvar_fraction: f64) -> Result<Aggregation, crate::Error> {
+        match self {
+            RobustMode::WorstCase => Ok(Aggregation::WorstCase),
+            RobustMode::Cvar if cvar_fraction > 0.0 && cvar_fraction <= 1.0 => {
+                Ok(Aggregation::Cvar {
+                    fraction: cvar_fraction,
+                })
+            }
+            RobustMode::Cvar => {
+                Err(format!("CVaR fraction {} is not in 0 < x <= 1", cvar_fraction).into())
+            }
+        }
+    }
+}
+
+/// How the errors of the scenarios are combined into the score the optimizer minimizes
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    /// The error of the worst scenario
+    WorstCase,
+    /// The conditional value at risk: the mean error of the worst `fraction` of the scenarios,
+    /// and at least of the worst one. A fraction of 1 is the plain mean
+    Cvar { fraction: f64 },
+}
+
+impl Aggregation {
+    pub fn aggregate(&self, errors: &[f64]) -> f64 {
+        // A scenario whose simulation could not be scored is as bad as it gets
+        if errors.is_empty() || errors.iter().any(|e| e.is_nan()) {
+            return f64::NAN;
+        }
+        let mut sorted = errors.to_vec();
+        sorted.sort_by(|a, b| b.total_cmp(a));
+        let count = match self {
+            Aggregation::WorstCase => 1,
+            Aggregation::Cvar { fraction } => {
+                ((fraction * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len())
+            }
+        };
+        sorted[..count].iter().sum::<f64>() / count as f64
+    }
+}
+
+/// A named set of simulation arguments that sets up an attack, such as
+/// `jamming:attack=jamming,attackIntensity=0.3`
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct AttackScenario {
+    pub name: String,
+    /// Added to the simulation arguments, by name. Empty for the clean scenario
+    pub arguments: IndexMap<String, String>,
+}
+
+impl FromStr for AttackScenario {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arguments) = s.split_once(':').unwrap_or((s, ""));
+        if name.is_empty() {
+            return Err(format!("Expected NAME[:ARG=VALUE,...], got `{}`", s));
+        }
+        let arguments = arguments
+            .split(',')
+            .filter(|argument| !argument.is_empty())
+            .map(|argument| {
+                let argument: ScenarioArgument = argument.parse()?;
+                Ok((argument.name, argument.value))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            name: name.to_owned(),
+            arguments,
+        })
+    }
+}
+
+/// The scenarios used when none are configured: a clean run and each attack the simulation
+/// knows at a moderate intensity
+pub fn default_scenarios() -> Vec<AttackScenario> {
+    [
+        "clean",
+        "takeover:attack=takeover",
+        "jamming:attack=jamming,attackIntensity=0.5",
+        "spoofing:attack=spoofing,attackIntensity=0.5",
+    ]
+    .iter()
+    .map(|s| s.parse().unwrap())
+    .collect()
+}
+
+/// Robust optimization settings. Every point is simulated in each scenario, and the optimizer
+/// minimizes the aggregate of the mean error of each scenario
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Robust {
+    pub aggregation: Aggregation,
+    pub scenarios: Vec<AttackScenario>,
+}
+
+impl Robust {
+    /// Uses [`default_scenarios`] if `scenarios` is empty
+    pub fn new(
+        aggregation: Aggregation,
+        mut scenarios: Vec<AttackScenario>,
+    ) -> Result<Self, crate::Error> {
+        if scenarios.is_empty() {
+            scenarios = default_scenarios();
+        }
+        for (i, scenario) in scenarios.iter().enumerate() {
+            if scenarios[..i].iter().any(|s| s.name == scenario.name) {
+                return Err(format!("Attack scenario {} is given twice", scenario.name).into());
+            }
+        }
+        Ok(Self {
+            aggregation,
+            scenarios,
+        })
+    }
+
+    /// Combines values measured in the scenarios, given with the name of the scenario each one
+    /// was simulated in. Returns the mean of each scenario, in the configured order, and their
+    /// aggregate
+    pub fn score<'a>(
+        &self,
+        values: impl Iterator<Item = (&'a str, f64)> + Clone,
+    ) -> (IndexMap<String, f64>, f64) {
+        let means: IndexMap<String, f64> = self
+            .scenarios
+            .iter()
+            .filter_map(|scenario| {
+                let values: Vec<f64> = values
+                    .clone()
+                    .filter(|(name, _)| *name == scenario.name)
+                    .map(|(_, value)| value)
+                    .collect();
+                if values.is_empty() {
+                    None
+                } else {
+                    let mean = values.iter().sum::<f64>() / values.len() as f64;
+                    Some((scenario.name.clone(), mean))
+                }
+            })
+            .collect();
+        let errors: Vec<f64> = means.values().copied().collect();
+        (means, self.aggregation.aggregate(&errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_scenarios() {
+        let errors = [3.0, 9.0, 1.0, 5.0];
+        assert_eq!(Aggregation::WorstCase.aggregate(&errors), 9.0);
+        assert_eq!(Aggregation::Cvar { fraction: 0.5 }.aggregate(&errors), 7.0);
+        assert_eq!(Aggregation::Cvar { fraction: 1.0 }.aggregate(&errors), 4.5);
+        // Always at least the worst scenario
+        assert_eq!(Aggregation::Cvar { fraction: 0.01 }.aggregate(&errors), 9.0);
+        assert!(Aggregation::WorstCase.aggregate(&[1.0, f64::NAN]).is_nan());
+        assert!(RobustMode::Cvar.aggregation(0.0).is_err());
+
+        let robust = Robust::new(Aggregation::WorstCase, Vec::new()).unwrap();
+        let values = [
+            ("jamming", 4.0),
+            ("clean", 1.0),
+            ("jamming", 6.0),
+            ("clean", 3.0),
+        ];
+        let (means, score) = robust.score(values.iter().copied());
+        assert_eq!(means.keys().collect::<Vec<_>>(), vec!["clean", "jamming"]);
+        assert_eq!(means["clean"], 2.0);
+        assert_eq!(score, 5.0);
+    }
+
+    #[test]
+    fn parse_scenario() {
+        let scenario: AttackScenario = "jam:attack=jamming,attackIntensity=0.3".parse().unwrap();
+        assert_eq!(scenario.name, "jam");
+        assert_eq!(scenario.arguments["attackIntensity"], "0.3");
+        let clean: AttackScenario = "clean".parse().unwrap();
+        assert!(clean.arguments.is_empty());
+        assert!(":attack=jamming".parse::<AttackScenario>().is_err());
+        assert!("jam:attack".parse::<AttackScenario>().is_err());
+
+        let twice = vec![clean.clone(), clean];
+        assert!(Robust::new(Aggregation::WorstCase, twice).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Global sensitivity analysis: how much each parameter contributes to the error and to each of
+//! the terms it is made of.
+//!
+//! A [`Design`] is a fixed set of points in the unit hypercube, laid out so that the effect of
+//! every parameter can be estimated once all of them have been simulated
+
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+/// Number of levels of the Morris grid
+const MORRIS_LEVELS: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    /// Variance based first order and total indices, estimated with Saltelli's scheme
+    Sobol,
+    /// Elementary effects along random one-at-a-time trajectories
+    Morris,
+}
+
+/// The outputs of simulating one point of a design
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Evaluation {
+    pub error: f64,
+    pub objectives: Vec<f64>,
+}
+
+/// The points to simulate and the results that have come in so far
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Design {
+    pub method: Method,
+    pub dimensions: usize,
+    /// The points are grouped into blocks of `block_size`: for Sobol the `A` and `B` samples and
+    /// every `A` sample with one coordinate from `B`, for Morris one trajectory
+    pub block_size: usize,
+    pub points: Vec<Vec<f64>>,
+    pub evaluations: Vec<Option<Evaluation>>,
+}
+
+/// How much one parameter matters for one output
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(untagged)]
+pub enum Sensitivity {
+    Sobol {
+        /// Fraction of the variance caused by the parameter alone
+        first_order: f64,
+        /// Fraction of the variance caused by the parameter including its interactions
+        total: f64,
+    },
+    Morris {
+        /// Mean absolute elementary effect
+        mu_star: f64,
+        /// Standard deviation of the elementary effects. High values mean the effect depends
+        /// on the other parameters or is non-linear
+        sigma: f64,
+    },
+}
+
+impl Design {
+    /// Creates a Sobol design with `samples` base samples, which takes `samples * (dimensions + 2)`
+    /// simulations
+    pub fn sobol(samples: usize, dimensions: usize, rng: &mut dyn RngCore) -> Self {
+        let mut points = Vec::new();
+        for _ in 0..samples {
+            let a: Vec<f64> = (0..dimensions).map(|_| rng.gen()).collect();
+            let b: Vec<f64> = (0..dimensions).map(|_| rng.gen()).collect();
+            points.push(a.clone());
+            points.push(b.clone());
+            for i in 0..dimensions {
+                let mut ab = a.clone();
+                ab[i] = b[i];
+                points.push(ab);
+            }
+        }
+        Self::new(Method::Sobol, dimensions, dimensions + 2, points)
+    }
+
+    /// Creates a Morris design with `trajectories` trajectories, which takes
+    /// `trajectories * (dimensions + 1)` simulations
+    pub fn morris(trajectories: usize, dimensions: usize, rng: &mut dyn RngCore) -> Self {
+        let step = morris_step();
+        // Starting levels from which a full step stays inside the unit interval
+        let starts: Vec<f64> = (0..MORRIS_LEVELS)
+            .map(|level| level as f64 / (MORRIS_LEVELS - 1) as f64)
+            .filter(|x| *x + step <= 1.0 + 1e-9)
+            .collect();
+
+        let mut points = Vec::new();
+        for _ in 0..trajectories {
+            let mut point: Vec<f64> = (0..dimensions)
+                .map(|_| *starts.choose(rng).unwrap())
+                .collect();
+            let mut order: Vec<usize> = (0..dimensions).collect();
+            order.shuffle(rng);
+            points.push(point.clone());
+            for i in order {
+                point[i] += step;
+                points.push(point.clone());
+            }
+        }
+        Self::new(Method::Morris, dimensions, dimensions + 1, points)
+    }
+
+    fn new(method: Method, dimensions: usize, block_size: usize, points: Vec<Vec<f64>>) -> Self {
+        Self {
+            method,
+            dimensions,
+            block_size,
+            evaluations: vec![None; points.len()],
+            points,
+        }
+    }
+
+    /// Returns true once every point has been simulated
+    pub fn is_complete(&self) -> bool {
+        self.evaluations.iter().all(Option::is_some)
+    }
+
+    /// Estimates the sensitivity of the output picked by `output` to every parameter. Blocks with
+    /// missing or NaN outputs are skipped
+    pub fn analyze(&self, output: impl Fn(&Evaluation) -> f64) -> Vec<Sensitivity> {
+        // The outputs of every complete block, along with the index of the block
+        let blocks: Vec<(usize, Vec<f64>)> = self
+            .evaluations
+            .chunks(self.block_size)
+            .enumerate()
+            .filter_map(|(index, block)| {
+                block
+                    .iter()
+                    .map(|e| e.as_ref().map(&output).filter(|v| !v.is_nan()))
+                    .collect::<Option<Vec<f64>>>()
+                    .map(|values| (index, values))
+            })
+            .collect();
+        match self.method {
+            Method::Sobol => {
+                let blocks: Vec<Vec<f64>> = blocks.into_iter().map(|(_, values)| values).collect();
+                sobol_indices(&blocks, self.dimensions)
+            }
+            Method::Morris => self.morris_effects(&blocks),
+        }
+    }
+
+    fn morris_effects(&self, blocks: &[(usize, Vec<f64>)]) -> Vec<Sensitivity> {
+        let step = morris_step();
+        let mut effects = vec![Vec::new(); self.dimensions];
+        for (index, values) in blocks {
+            let points = &self.points[index * self.block_size..(index + 1) * self.block_size];
+            for (pair, values) in points.windows(2).zip(values.windows(2)) {
+                // Exactly one coordinate changes between consecutive points of a trajectory
+                let moved = (0..self.dimensions)
+                    .find(|i| pair[0][*i] != pair[1][*i])
+                    .unwrap();
+                effects[moved].push((values[1] - values[0]) / step);
+            }
+        }
+        effects
+            .iter()
+            .map(|effects| {
+                if effects.is_empty() {
+                    return Sensitivity::Morris {
+                        mu_star: f64::NAN,
+                        sigma: f64::NAN,
+                    };
+                }
+                let summary = crate::util::summarize(effects);
+                Sensitivity::Morris {
+                    mu_star: effects.iter().map(|e| e.abs()).sum::<f64>() / effects.len() as f64,
+                    sigma: summary.variance.sqrt(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The distance moved along one axis in a Morris trajectory
+fn morris_step() -> f64 {
+    MORRIS_LEVELS as f64 / (2.0 * (MORRIS_LEVELS - 1) as f64)
+}
+
+/// Saltelli (2010) first order and Jansen total indices. Each block holds `f(A)`, `f(B)` and
+/// `f(A with coordinate i from B)` for every `i`
+fn sobol_indices(blocks: &[Vec<f64>], dimensions: usize) -> Vec<Sensitivity> {
+    let n = blocks.len() as f64;
+    let outputs: Vec<f64> = blocks.iter().flat_map(|b| b[..2].to_vec()).collect();
+    if outputs.len() < 2 {
+        return vec![
+            Sensitivity::Sobol {
+                first_order: f64::NAN,
+                total: f64::NAN,
+            };
+            dimensions
+        ];
+    }
+    let summary = crate::util::summarize(&outputs);
+    let (mean, variance) = (summary.mean, summary.variance);
+
+    (0..dimensions)
+        .map(|i| {
+            // Centering f(B) doesn't change the expectation but makes the estimate much less
+            // noisy when the outputs are far from zero
+            let first_order = blocks
+                .iter()
+                .map(|b| (b[1] - mean) * (b[i + 2] - b[0]))
+                .sum::<f64>()
+                / n
+                / variance;
+            let total = blocks
+                .iter()
+                .map(|b| (b[0] - b[i + 2]).powi(2))
+                .sum::<f64>()
+                / (2.0 * n)
+                / variance;
+            Sensitivity::Sobol { first_order, total }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn evaluate(design: &mut Design, f: impl Fn(&[f64]) -> f64) {
+        for (point, evaluation) in design.points.iter().zip(design.evaluations.iter_mut()) {
+            *evaluation = Some(Evaluation {
+                error: f(point),
+                objectives: Vec::new(),
+            });
+        }
+    }
+
+    #[test]
+    fn sobol_finds_important_parameter() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(11);
+        let mut design = Design::sobol(2000, 3, &mut rng);
+        assert_eq!(design.points.len(), 2000 * 5);
+        assert!(!design.is_complete());
+        // The first parameter explains 16/17 of the variance and the third does nothing
+        evaluate(&mut design, |p| 4.0 * p[0] + p[1]);
+        assert!(design.is_complete());
+
+        let indices = design.analyze(|e| e.error);
+        let expected = [16.0 / 17.0, 1.0 / 17.0, 0.0];
+        for (index, expected) in indices.iter().zip(expected.iter()) {
+            match index {
+                Sensitivity::Sobol { first_order, total } => {
+                    assert!((first_order - expected).abs() < 0.05, "{:?}", indices);
+                    assert!((total - expected).abs() < 0.05, "{:?}", indices);
+                }
+                _ => panic!("expected Sobol indices"),
+            }
+        }
+    }
+
+    #[test]
+    fn morris_trajectories() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(4);
+        let mut design = Design::morris(10, 2, &mut rng);
+        assert_eq!(design.points.len(), 10 * 3);
+        for trajectory in design.points.chunks(3) {
+            for pair in trajectory.windows(2) {
+                let moved = (0..2).filter(|i| pair[0][*i] != pair[1][*i]).count();
+                assert_eq!(moved, 1);
+            }
+        }
+
+        evaluate(&mut design, |p| 3.0 * p[0] - p[1] * p[1]);
+        // Drop a trajectory to check that incomplete ones are skipped
+        design.evaluations[4] = None;
+        let effects = design.analyze(|e| e.error);
+        match effects[0] {
+            Sensitivity::Morris { mu_star, sigma } => {
+                assert!((mu_star - 3.0).abs() < 1e-9);
+                assert!(sigma < 1e-9);
+            }
+            _ => panic!("expected Morris effects"),
+        }
+        match effects[1] {
+            Sensitivity::Morris { mu_star, .. } => assert!(mu_star > 0.0 && mu_star < 2.0),
+            _ => panic!("expected Morris effects"),
+        }
+    }
+}
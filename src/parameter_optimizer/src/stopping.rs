@@ -0,0 +1,196 @@
+//! Rules that decide when an optimization campaign has run long enough
+
+use std::fmt;
+use std::time::Duration;
+
+/// The conditions under which the runners are stopped. Every enabled rule is checked whenever a
+/// run finishes, and the first one that is met stops the campaign
+#[derive(Clone, Debug)]
+pub struct StoppingRules {
+    /// Stop after this many full length simulations
+    pub max_simulations: usize,
+    /// Stop once this much time has passed since the runners started
+    pub wall_clock: Option<Duration>,
+    /// Stop when the best error has not improved in this many runs
+    pub patience: Option<usize>,
+    /// Stop when the best error improved by less than this fraction during the last
+    /// `improvement_window` runs
+    pub min_improvement: Option<f64>,
+    pub improvement_window: usize,
+    /// Stop as soon as a run reaches this error
+    pub target_error: Option<f64>,
+}
+
+impl Default for StoppingRules {
+    fn default() -> Self {
+        Self {
+            max_simulations: 1000,
+            wall_clock: None,
+            patience: None,
+            min_improvement: None,
+            improvement_window: 100,
+            target_error: None,
+        }
+    }
+}
+
+/// Why a campaign stopped
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    MaxSimulations,
+    WallClock,
+    NoImprovement,
+    SlowImprovement,
+    TargetReached,
+    /// Every point of a sensitivity analysis design has been simulated
+    DesignComplete,
+    /// The local refinement can't get any closer to the optimum
+    Converged,
+    /// Stopped with Control-C
+    Interrupted,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::MaxSimulations => "reached the maximum number of simulations",
+            StopReason::WallClock => "ran out of time",
+            StopReason::NoImprovement => "the best error stopped improving",
+            StopReason::SlowImprovement => "the best error improved too slowly",
+            StopReason::TargetReached => "reached the target error",
+            StopReason::DesignComplete => "simulated every point of its design",
+            StopReason::Converged => "converged",
+            StopReason::Interrupted => "interrupted",
+        })
+    }
+}
+
+impl StoppingRules {
+    /// Returns the reason to stop, if any. `errors` are the errors of the finished full length
+    /// runs in the order they finished, `simulations` is how many full length simulations have
+    /// been run and `elapsed` is the time since the runners started
+    pub fn check(&self, errors: &[f64], simulations: f64, elapsed: Duration) -> Option<StopReason> {
+        let best = errors
+            .iter()
+            .copied()
+            .filter(|e| !e.is_nan())
+            .fold(f64::INFINITY, f64::min);
+
+        if matches!(self.target_error, Some(target) if best <= target) {
+            return Some(StopReason::TargetReached);
+        }
+        if simulations >= self.max_simulations as f64 {
+            return Some(StopReason::MaxSimulations);
+        }
+        if matches!(self.wall_clock, Some(limit) if elapsed >= limit) {
+            return Some(StopReason::WallClock);
+        }
+        if let Some(patience) = self.patience {
+            // NaN never compares equal so failed runs are never the best
+            let runs_since_best = match errors.iter().position(|e| *e == best) {
+                Some(index) => errors.len() - 1 - index,
+                None => errors.len(),
+            };
+            if runs_since_best >= patience {
+                return Some(StopReason::NoImprovement);
+            }
+        }
+        if let Some(min_improvement) = self.min_improvement {
+            if errors.len() > self.improvement_window {
+                let best_before = errors[..errors.len() - self.improvement_window]
+                    .iter()
+                    .copied()
+                    .filter(|e| !e.is_nan())
+                    .fold(f64::INFINITY, f64::min);
+                if best_before.is_finite() {
+                    let improvement = (best_before - best) / best_before.abs().max(f64::EPSILON);
+                    if improvement < min_improvement {
+                        return Some(StopReason::SlowImprovement);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Converts a number of minutes given on the command line into a duration. `Duration` can't hold
+/// negative, NaN or infinite amounts of time
+pub fn minutes(minutes: f64) -> Result<Duration, crate::Error> {
+    if !minutes.is_finite() || minutes <= 0.0 {
+        return Err(format!("{} minutes is not a positive length of time", minutes).into());
+    }
+    Ok(Duration::from_secs_f64(minutes * 60.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let rules = StoppingRules {
+            max_simulations: 10,
+            wall_clock: Some(Duration::from_secs(60)),
+            target_error: Some(1.0),
+            ..Default::default()
+        };
+        let minute = Duration::from_secs(60);
+        assert_eq!(rules.check(&[5.0, 3.0], 2.0, Duration::ZERO), None);
+        assert_eq!(
+            rules.check(&[5.0, 3.0], 10.0, Duration::ZERO),
+            Some(StopReason::MaxSimulations)
+        );
+        assert_eq!(
+            rules.check(&[5.0, 3.0], 2.0, minute),
+            Some(StopReason::WallClock)
+        );
+        assert_eq!(
+            rules.check(&[5.0, 0.5], 2.0, Duration::ZERO),
+            Some(StopReason::TargetReached)
+        );
+    }
+
+    #[test]
+    fn patience() {
+        let rules = StoppingRules {
+            patience: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(rules.check(&[], 0.0, Duration::ZERO), None);
+        assert_eq!(
+            rules.check(&[5.0, 4.0, 6.0, 7.0], 4.0, Duration::ZERO),
+            None
+        );
+        assert_eq!(
+            rules.check(&[5.0, 4.0, 6.0, f64::NAN, 4.0], 5.0, Duration::ZERO),
+            Some(StopReason::NoImprovement)
+        );
+    }
+
+    #[test]
+    fn slow_improvement() {
+        let rules = StoppingRules {
+            min_improvement: Some(0.05),
+            improvement_window: 2,
+            ..Default::default()
+        };
+        assert_eq!(rules.check(&[10.0, 9.0], 2.0, Duration::ZERO), None);
+        // 10 -> 9 over the last two runs is a 10% improvement
+        assert_eq!(rules.check(&[10.0, 9.5, 9.0], 3.0, Duration::ZERO), None);
+        // 9 -> 8.9 is about 1%
+        assert_eq!(
+            rules.check(&[10.0, 9.0, 9.5, 8.9], 4.0, Duration::ZERO),
+            Some(StopReason::SlowImprovement)
+        );
+    }
+
+    #[test]
+    fn parse_minutes() {
+        assert_eq!(minutes(1.5).unwrap(), Duration::from_secs(90));
+        for bad in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(minutes(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Predicting the error of parameters that were never simulated, from the runs of a finished
+//! campaign
+
+use crate::gaussian_process::GaussianProcess;
+use crate::parameter::Parameter;
+
+use indexmap::IndexMap;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::path::Path;
+
+/// The most runs the model is fitted on. Fitting takes cubic time, so larger campaigns are
+/// thinned out: half of these are the best runs and the rest are picked at random
+const MAX_RUNS: usize = 400;
+
+/// Seed for picking the runs of large campaigns, so that the same file always gives the same model
+const THINNING_SEED: u64 = 0;
+
+/// What the model expects a simulation to give
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize)]
+pub struct Prediction {
+    pub error: f64,
+    /// Standard deviation of the predicted error. Grows away from the simulated runs
+    pub uncertainty: f64,
+}
+
+/// A Gaussian process fitted to the full length runs of a campaign
+pub struct Surrogate {
+    params: Vec<Parameter>,
+    model: GaussianProcess,
+}
+
+/// The parts of an output json needed to fit the model
+#[derive(serde::Deserialize)]
+struct Campaign {
+    params: Vec<Parameter>,
+    results: Vec<Run>,
+}
+
+#[derive(serde::Deserialize)]
+struct Run {
+    parameters: IndexMap<String, f64>,
+    #[serde(rename = "fitness", deserialize_with = "crate::util::nan_from_null")]
+    error: f64,
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    infeasible: Option<String>,
+}
+
+impl Surrogate {
+    /// Fits a model to the output json of a campaign
+    pub fn from_file(json_path: impl AsRef<Path>) -> Result<Self, crate::Error> {
+        let json = std::fs::read_to_string(json_path)?;
+        let campaign: Campaign = serde_json::from_str(&json)?;
+        let runs: Vec<(IndexMap<String, f64>, f64)> = full_length(campaign.results)
+            .into_iter()
+            .filter(|run| run.infeasible.is_none())
+            .map(|run| (run.parameters, run.error))
+            .collect();
+        Self::fit(campaign.params, &runs)
+    }
+
+    /// Fits a model to runs given as their parameters and error. Runs missing one of `params`
+    /// or with a NaN error are skipped
+    pub fn fit(
+        params: Vec<Parameter>,
+        runs: &[(IndexMap<String, f64>, f64)],
+    ) -> Result<Self, crate::Error> {
+        let mut usable: Vec<(Vec<f64>, f64)> = runs
+            .iter()
+            .filter(|(_, error)| error.is_finite())
+            .filter_map(|(parameters, error)| {
+                let point: Option<Vec<f64>> = params
+                    .iter()
+                    .map(|p| parameters.get(&p.name).map(|v| p.normalize(*v)))
+                    .collect();
+                point.map(|point| (point, *error))
+            })
+            .collect();
+        if usable.len() < 2 {
+            return Err("Need at least two finished runs to fit a model".into());
+        }
+
+        let mut rng = StdRng::seed_from_u64(THINNING_SEED);
+        if usable.len() > MAX_RUNS {
+            usable.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            let mut rest = usable.split_off(MAX_RUNS / 2);
+            rest.shuffle(&mut rng);
+            rest.truncate(MAX_RUNS - usable.len());
+            usable.extend(rest);
+        }
+        let (points, values): (Vec<Vec<f64>>, Vec<f64>) = usable.into_iter().unzip();
+        let model = GaussianProcess::fit_hyperparameters(&points, &values, None, &mut rng)?;
+        Ok(Self { params, model })
+    }
+
+    /// Predicts the error of the parameter values in `values`, which must give a value inside the
+    /// range of every parameter of the campaign
+    pub fn predict(&self, values: &IndexMap<String, f64>) -> Result<Prediction, crate::Error> {
+        let mut point = Vec::with_capacity(self.params.len());
+        for param in &self.params {
+            let value = *values
+                .get(&param.name)
+                .ok_or_else(|| format!("Missing a value for {}", param.name))?;
+            if !(param.min..=param.max).contains(&value) {
+                return Err(format!(
+                    "{}={} is outside the range {}..{} of the campaign",
+                    param.name, value, param.min, param.max
+                )
+                .into());
+            }
+            point.push(param.normalize(value));
+        }
+        if let Some(name) = values
+            .keys()
+            .find(|name| !self.params.iter().any(|p| p.name == **name))
+        {
+            return Err(format!("{} was not optimized in this campaign", name).into());
+        }
+        let (error, variance) = self.model.predict(&point);
+        Ok(Prediction {
+            error,
+            uncertainty: variance.sqrt(),
+        })
+    }
+}
+
+/// Keeps the runs that simulated the longest duration, dropping multi-fidelity screening runs
+fn full_length(runs: Vec<Run>) -> Vec<Run> {
+    let longest = runs
+        .iter()
+        .filter_map(|r| r.duration)
+        .fold(f64::NEG_INFINITY, f64::max);
+    runs.into_iter()
+        .filter(|r| r.duration.is_none_or(|d| d >= longest))
+        .collect()
+}
+
+/// Parses parameter values given as `NAME=VALUE`
+pub fn parse_values(values: &[String]) -> Result<IndexMap<String, f64>, crate::Error> {
+    values
+        .iter()
+        .map(|value| match value.split_once('=') {
+            Some((name, number)) => Ok((
+                name.to_owned(),
+                number
+                    .parse::<f64>()
+                    .map_err(|err| format!("Bad value in `{}`: {}", value, err))?,
+            )),
+            None => Err(format!("Expected NAME=VALUE, got `{}`", value).into()),
+        })
+        .collect()
+}
+
+/// Predicts the error of `values` from the runs in the output json `json_path`
+pub fn predict(
+    json_path: impl AsRef<Path>,
+    values: &IndexMap<String, f64>,
+) -> Result<Prediction, crate::Error> {
+    Surrogate::from_file(json_path)?.predict(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn predicts_unsimulated_parameters() {
+        let error = |a: f64, r: f64| (a - 4.0).powi(2) + 0.5 * (r - 7.0).powi(2);
+        let params = vec![
+            Parameter::float("a", 0.0, 10.0),
+            Parameter::float("r", 0.0, 10.0),
+        ];
+        let mut runs = Vec::new();
+        for i in 0..8 {
+            for j in 0..8 {
+                let (a, r) = (i as f64 * 10.0 / 7.0, j as f64 * 10.0 / 7.0);
+                runs.push((
+                    parse_values(&[format!("a={}", a), format!("r={}", r)]).unwrap(),
+                    error(a, r),
+                ));
+            }
+        }
+        let surrogate = Surrogate::fit(params, &runs).unwrap();
+
+        let values = parse_values(&["a=4.2".to_owned(), "r=7.1".to_owned()]).unwrap();
+        let prediction = surrogate.predict(&values).unwrap();
+        assert!(
+            (prediction.error - error(4.2, 7.1)).abs() < 0.5,
+            "{:?}",
+            prediction
+        );
+        assert!(prediction.uncertainty < 0.5, "{:?}", prediction);
+
+        let outside = parse_values(&["a=12".to_owned(), "r=7.1".to_owned()]).unwrap();
+        assert!(surrogate.predict(&outside).is_err());
+        let missing = parse_values(&["a=4".to_owned()]).unwrap();
+        assert!(surrogate.predict(&missing).is_err());
+        assert!(parse_values(&["a".to_owned()]).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use indexmap::IndexMap;
 use std::process::Command;
 
 pub fn run_waf_command(
@@ -35,6 +36,15 @@ macro_rules! map {
     };
 }
 
+/// Mixes `stream` into `seed`, giving independent seeds for several random number generators that
+/// all derive from one seed. Uses the output function of SplitMix64
+pub fn derive_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
 fn lerp<T, F>(a: T, b: T, f: F) -> T
 where
     T: Copy,
@@ -107,6 +117,65 @@ where
     }
 }
 
+/// Two sided 95% quantiles of Student's t distribution for 1 to 30 degrees of freedom
+const T_QUANTILES_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// The mean of a few noisy samples along with how much it can be trusted
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub mean: f64,
+    /// Unbiased sample variance. Zero when there is only one sample
+    pub variance: f64,
+    /// Half width of the 95% confidence interval of the mean
+    pub confidence: f64,
+}
+
+pub fn summarize(values: &[f64]) -> Summary {
+    let n = values.len();
+    assert!(n > 0, "cannot summarize zero values");
+    let mean = values.iter().sum::<f64>() / n as f64;
+    if n == 1 {
+        return Summary {
+            mean,
+            variance: 0.0,
+            confidence: 0.0,
+        };
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    let t = T_QUANTILES_95.get(n - 2).copied().unwrap_or(1.96);
+    Summary {
+        mean,
+        variance,
+        confidence: t * (variance / n as f64).sqrt(),
+    }
+}
+
+/// Reads a number that may be NaN. Json has no NaN, so serde_json writes it as null and then
+/// refuses to read null back as a number. Use with `#[serde(deserialize_with = ...)]`
+pub fn nan_from_null<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<f64> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.unwrap_or(f64::NAN))
+}
+
+/// Like [`nan_from_null`] for the values of a map
+pub fn nans_from_null<'de, D>(deserializer: D) -> Result<IndexMap<String, f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values: IndexMap<String, Option<f64>> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .map(|(name, value)| (name, value.unwrap_or(f64::NAN)))
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -136,4 +205,52 @@ mod tests {
         let ranges: Vec<i32> = smoother.ranges().collect();
         assert_eq!(ranges.as_slice(), &[10, 10, 10, 15, 15, 15, 25, 25]);
     }
+
+    #[test]
+    fn derived_seeds() {
+        assert_eq!(derive_seed(7, 3), derive_seed(7, 3));
+        let seeds: Vec<u64> = (0..100).map(|stream| derive_seed(7, stream)).collect();
+        for (i, seed) in seeds.iter().enumerate() {
+            assert!(!seeds[i + 1..].contains(seed));
+            assert_ne!(*seed, derive_seed(8, i as u64));
+        }
+    }
+
+    #[test]
+    fn summary() {
+        let single = summarize(&[3.0]);
+        assert_eq!(
+            (single.mean, single.variance, single.confidence),
+            (3.0, 0.0, 0.0)
+        );
+
+        let summary = summarize(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(summary.mean, 2.5);
+        assert!((summary.variance - 5.0 / 3.0).abs() < 1e-12);
+        // t(3) * sqrt(variance / 4)
+        assert!((summary.confidence - 3.182 * (5.0f64 / 12.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn nan_round_trip() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Run {
+            #[serde(deserialize_with = "nan_from_null")]
+            error: f64,
+            #[serde(deserialize_with = "nans_from_null")]
+            objectives: IndexMap<String, f64>,
+        }
+        let mut objectives = IndexMap::new();
+        objectives.insert("velocity".to_owned(), f64::NAN);
+        objectives.insert("spacing".to_owned(), 2.0);
+        let json = serde_json::to_string(&Run {
+            error: f64::NAN,
+            objectives,
+        })
+        .unwrap();
+        let run: Run = serde_json::from_str(&json).unwrap();
+        assert!(run.error.is_nan());
+        assert!(run.objectives["velocity"].is_nan());
+        assert_eq!(run.objectives["spacing"], 2.0);
+    }
 }
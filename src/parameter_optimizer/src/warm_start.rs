@@ -0,0 +1,258 @@
+//! Seeding a new campaign with the runs of earlier ones, so that campaigns for similar scenarios
+//! build on each other instead of starting from scratch
+
+use crate::parameter::{Parameter, ParameterType};
+
+use indexmap::IndexMap;
+use rand::{Rng, RngCore};
+use std::str::FromStr;
+
+/// A simulation argument that is not optimized, such as `pNodes=8`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScenarioArgument {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for ScenarioArgument {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches("--").split_once('=') {
+            Some((name, value)) if !name.is_empty() && !value.is_empty() => Ok(Self {
+                name: name.to_owned(),
+                value: value.to_owned(),
+            }),
+            _ => Err(format!("Expected NAME=VALUE, got `{}`", s)),
+        }
+    }
+}
+
+impl ScenarioArgument {
+    /// Returns true if `scenario` has the same value for this argument. Numbers are compared by
+    /// value so that `8` matches `8.0`
+    pub fn matches(&self, scenario: &IndexMap<String, String>) -> bool {
+        match scenario.get(&self.name) {
+            Some(value) => match (value.parse::<f64>(), self.value.parse::<f64>()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => *value == self.value,
+            },
+            None => false,
+        }
+    }
+}
+
+/// Which earlier campaigns to learn from and how many of their runs
+#[derive(Clone, Debug)]
+pub struct WarmStart {
+    /// Output json files of earlier campaigns
+    pub files: Vec<String>,
+    /// Only files whose scenario matches all of these are used
+    pub filters: Vec<ScenarioArgument>,
+    /// The fraction of the earlier runs that are used, between 0 and 1. Each run is picked at
+    /// random, and the ones that are used count as much as the optimizer's own. Lower values
+    /// make the optimizer lean less on the earlier campaigns compared to its own runs
+    pub fraction: f64,
+}
+
+/// A run of an earlier campaign that is told to the optimizer before the first simulation
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Prior {
+    /// The file the run was read from
+    pub source: String,
+    /// The values of the parameters being optimized now
+    pub parameters: IndexMap<String, f64>,
+    #[serde(rename = "fitness", deserialize_with = "crate::util::nan_from_null")]
+    pub error: f64,
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    pub objectives: IndexMap<String, f64>,
+}
+
+/// The parts of an output json needed to reuse its runs
+#[derive(serde::Deserialize)]
+struct PriorCampaign {
+    #[serde(default)]
+    scenario: Option<IndexMap<String, String>>,
+    results: Vec<PriorRun>,
+}
+
+#[derive(serde::Deserialize)]
+struct PriorRun {
+    parameters: IndexMap<String, f64>,
+    #[serde(rename = "fitness", deserialize_with = "crate::util::nan_from_null")]
+    error: f64,
+    #[serde(default, deserialize_with = "crate::util::nans_from_null")]
+    objectives: IndexMap<String, f64>,
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    infeasible: Option<String>,
+}
+
+impl WarmStart {
+    /// Reads the runs of every file whose scenario passes the filters. `params` are the
+    /// parameters of the new campaign, `full_duration` is the length of a full simulation and
+    /// `default_scenario` is assumed for files written before the scenario was recorded
+    pub fn load(
+        &self,
+        params: &[Parameter],
+        full_duration: f64,
+        default_scenario: &IndexMap<String, String>,
+        rng: &mut dyn RngCore,
+    ) -> Result<Vec<Prior>, crate::Error> {
+        if !(0.0..=1.0).contains(&self.fraction) {
+            return Err(format!(
+                "Warm start fraction {} is not between 0 and 1",
+                self.fraction
+            )
+            .into());
+        }
+        let mut priors = Vec::new();
+        for file in &self.files {
+            let json = std::fs::read_to_string(file)?;
+            let campaign: PriorCampaign = serde_json::from_str(&json)
+                .map_err(|err| format!("Failed to read {}: {}", file, err))?;
+            let selected =
+                self.select(file, campaign, params, full_duration, default_scenario, rng);
+            println!("Warm starting with {} runs from {}", selected.len(), file);
+            priors.extend(selected);
+        }
+        Ok(priors)
+    }
+
+    fn select(
+        &self,
+        source: &str,
+        campaign: PriorCampaign,
+        params: &[Parameter],
+        full_duration: f64,
+        default_scenario: &IndexMap<String, String>,
+        rng: &mut dyn RngCore,
+    ) -> Vec<Prior> {
+        let mut priors = Vec::new();
+        let scenario = campaign
+            .scenario
+            .unwrap_or_else(|| default_scenario.clone());
+        if !self.filters.iter().all(|filter| filter.matches(&scenario)) {
+            return priors;
+        }
+        for run in campaign.results {
+            // Infeasible and screening runs say little about the full length simulation
+            if run.error.is_nan()
+                || run.infeasible.is_some()
+                || run.duration.unwrap_or(full_duration) != full_duration
+            {
+                continue;
+            }
+            // A parameter that the earlier campaign did not optimize had its scenario value
+            let parameters: Option<IndexMap<String, f64>> = params
+                .iter()
+                .map(|p| {
+                    let value =
+                        run.parameters.get(&p.name).copied().or_else(|| {
+                            scenario.get(&p.name).and_then(|v| v.parse::<f64>().ok())
+                        })?;
+                    Some((p.name.clone(), value)).filter(|_| in_bounds(p, value))
+                })
+                .collect();
+            if let Some(parameters) = parameters {
+                if rng.gen_bool(self.fraction) {
+                    priors.push(Prior {
+                        source: source.to_owned(),
+                        parameters,
+                        error: run.error,
+                        objectives: run.objectives,
+                    });
+                }
+            }
+        }
+        priors
+    }
+}
+
+/// Returns true if the parameter can take `value`. Runs outside the new bounds are dropped rather
+/// than clamped onto the edge, where they would misrepresent the error there
+fn in_bounds(param: &Parameter, value: f64) -> bool {
+    match &param.kind {
+        ParameterType::Categorical(choices) => choices.contains(&value),
+        _ => (param.min..=param.max).contains(&value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn campaign(scenario: Option<&str>) -> PriorCampaign {
+        let scenario = scenario.map(|s| format!(r#""scenario": {},"#, s));
+        serde_json::from_str(&format!(
+            r#"{{ {}
+                "results": [
+                    {{ "parameters": {{ "a": 1.0, "r": 2.0 }}, "fitness": 5.0 }},
+                    {{ "parameters": {{ "a": 3.0, "r": 4.0 }}, "fitness": 6.0, "duration": 20.0 }},
+                    {{ "parameters": {{ "a": 30.0, "r": 4.0 }}, "fitness": 7.0 }},
+                    {{ "parameters": {{ "a": 5.0 }}, "fitness": 8.0 }}
+                ]
+            }}"#,
+            scenario.unwrap_or_default()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn filters_and_maps_runs() {
+        let params = vec![
+            Parameter::float("a", 0.0, 18.0),
+            Parameter::float("r", 0.0, 18.0),
+            Parameter::float("pNodes", 4.0, 16.0),
+        ];
+        let mut default_scenario = IndexMap::new();
+        default_scenario.insert("pNodes".to_owned(), "8".to_owned());
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let mut warm = WarmStart {
+            files: Vec::new(),
+            filters: vec!["pNodes=8.0".parse().unwrap()],
+            fraction: 1.0,
+        };
+
+        let select = |warm: &WarmStart, scenario, rng: &mut rand::rngs::StdRng| {
+            warm.select(
+                "old.json",
+                campaign(scenario),
+                &params,
+                180.0,
+                &default_scenario,
+                rng,
+            )
+        };
+        // Files without a scenario get the default one
+        let priors = select(&warm, None, &mut rng);
+        // The screening run, the run outside the bounds and the run missing `r` are dropped
+        assert_eq!(priors.len(), 1);
+        assert_eq!(priors[0].error, 5.0);
+        assert_eq!(
+            priors[0].parameters.values().copied().collect::<Vec<_>>(),
+            vec![1.0, 2.0, 8.0]
+        );
+
+        let other_scenario = Some(r#"{ "pNodes": "12" }"#);
+        assert!(select(&warm, other_scenario, &mut rng).is_empty());
+        warm.filters.clear();
+        assert_eq!(
+            select(&warm, other_scenario, &mut rng)[0].parameters["pNodes"],
+            12.0
+        );
+        warm.fraction = 0.0;
+        assert!(select(&warm, other_scenario, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn parse_argument() {
+        let argument: ScenarioArgument = "--spawnRadius=8.5".parse().unwrap();
+        assert_eq!(argument.name, "spawnRadius");
+        assert_eq!(argument.value, "8.5");
+        assert!("pNodes".parse::<ScenarioArgument>().is_err());
+        assert!("=3".parse::<ScenarioArgument>().is_err());
+    }
+}
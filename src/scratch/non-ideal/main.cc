@@ -113,7 +113,25 @@ LogPositions (const NodeContainer &nodes)
 bool
 ShouldDoCyberAttack ()
 {
-  return false;
+  return s_Parameters.attack != "none";
+}
+
+bool
+IsAttackActive ()
+{
+  double now = Simulator::Now ().GetSeconds ();
+  if (!ShouldDoCyberAttack () || now < s_Parameters.attackStart)
+    {
+      return false;
+    }
+  return s_Parameters.attackDuration <= 0 ||
+         now < s_Parameters.attackStart + s_Parameters.attackDuration;
+}
+
+bool
+IsAttacker (const Ipv4Address &address)
+{
+  return (address.Get () & 0xFF) == s_Parameters.attackNode;
 }
 
 int
@@ -142,8 +160,25 @@ main (int argc, char *argv[])
 
   cmd.AddValue ("positionsFile", "Where to write uav positions to during the simulation",
                 s_Parameters.positionsFile);
+  cmd.AddValue ("attack", "Cyber attack to simulate: none, takeover, jamming or spoofing",
+                s_Parameters.attack);
+  cmd.AddValue ("attackNode", "Last byte of the address of the compromised node",
+                s_Parameters.attackNode);
+  cmd.AddValue ("attackStart", "When the attack starts (seconds)", s_Parameters.attackStart);
+  cmd.AddValue ("attackDuration", "How long the attack lasts (seconds). 0 lasts until the end",
+                s_Parameters.attackDuration);
+  cmd.AddValue ("attackIntensity",
+                "Chance that jamming drops a packet, or how far spoofed positions are moved as a "
+                "fraction of the spawn radius",
+                s_Parameters.attackIntensity);
   cmd.Parse (argc, argv);
 
+  const auto &attack = s_Parameters.attack;
+  if (attack != "none" && attack != "takeover" && attack != "jamming" && attack != "spoofing")
+    {
+      NS_FATAL_ERROR ("Unknown attack " << attack);
+    }
+
   //
   // Explicitly create the nodes required by the topology (shown above).
   //
@@ -7,6 +7,12 @@ void SetColor(const ns3::Ipv4Address& address, ns3::Vector color);
 
 bool ShouldDoCyberAttack();
 
+// True while the configured attack is running
+bool IsAttackActive();
+
+// True for the node that the attacker controls
+bool IsAttacker(const ns3::Ipv4Address& address);
+
 struct SimulationParameters
 {
   double a = 1.0;
@@ -19,6 +25,17 @@ struct SimulationParameters
   double packetInterval = 1.5;
   double calculateInterval = 0.01;
   std::string positionsFile = "positions.csv";
+
+  // One of none, takeover, jamming or spoofing
+  std::string attack = "none";
+  // Last byte of the address of the compromised node. .1 is the central node
+  uint32_t attackNode = 2;
+  double attackStart = 15;
+  // How long the attack lasts. 0 keeps it going until the end of the simulation
+  double attackDuration = 0;
+  // Between 0 and 1. The chance that jamming drops a packet, or how far spoofed positions are
+  // moved as a fraction of the spawn radius
+  double attackIntensity = 0.5;
 };
 
 extern SimulationParameters s_Parameters;
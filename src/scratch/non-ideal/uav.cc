@@ -139,19 +139,38 @@ UAV::StartApplication (void)
     SetColor(m_uavAddress, { 0.3, 0.7, 1.0 });
   }
 
-  uint32_t lowAddress = m_uavAddress.Get() & 0xFF;
-  if (ShouldDoCyberAttack() && lowAddress == 2) {
-    //Have the number 2 node be the cyber attack because .1 is the central node
-    Simulator::Schedule (Seconds(15.0), &UAV::Cyberattack, this);
+  m_jammingRng.seed(s_Parameters.seed ^ m_uavAddress.Get());
+
+  if (ShouldDoCyberAttack() && IsAttacker(m_uavAddress)) {
+    //The default attacker is node number 2 because .1 is the central node
+    Simulator::Schedule (Seconds(s_Parameters.attackStart), &UAV::Cyberattack, this);
+    if (s_Parameters.attackDuration > 0) {
+      Simulator::Schedule (Seconds(s_Parameters.attackStart + s_Parameters.attackDuration),
+                           &UAV::EndCyberattack, this);
+    }
   }
 }
 
 void UAV::Cyberattack() {
-  NS_LOG_INFO("CYBERATTACK");
-  m_uavType = UAVDataType::VIRTUAL_FORCES_CENTRAL_POSITION;
+  NS_LOG_INFO("CYBERATTACK " << s_Parameters.attack);
+  m_typeBeforeAttack = m_uavType;
+  if (s_Parameters.attack == "takeover") {
+    //Claim to be the central node so the others are drawn towards us
+    m_uavType = UAVDataType::VIRTUAL_FORCES_CENTRAL_POSITION;
+  }
   SetColor(m_uavAddress, Vector(1.0, 0.2, 0.2));
 }
 
+void UAV::EndCyberattack() {
+  NS_LOG_INFO("CYBERATTACK OVER");
+  m_uavType = m_typeBeforeAttack;
+  if (m_uavType == UAVDataType::VIRTUAL_FORCES_CENTRAL_POSITION) {
+    SetColor(m_uavAddress, { 0.3, 0.7, 1.0 });
+  } else {
+    SetColor(m_uavAddress, { 1.0, 1.0, 1.0 });
+  }
+}
+
 void
 UAV::StopApplication ()
 {
@@ -187,6 +206,12 @@ UAV::HandleRead (Ptr<Socket> socket)
       if (ipv4Addr == m_uavAddress) {
         continue;
       }
+      if (s_Parameters.attack == "jamming" && IsAttackActive()) {
+        std::bernoulli_distribution lost(s_Parameters.attackIntensity);
+        if (lost(m_jammingRng)) {
+          continue;
+        }
+      }
       m_packetRecvCount[ipv4Addr]++;
       
       UAVData data;
@@ -247,6 +272,10 @@ UAV::Send (void)
   UAVData payload;
   payload.position = mobilityModel->GetPosition();
   payload.type = m_uavType;
+  if (s_Parameters.attack == "spoofing" && IsAttackActive() && IsAttacker(m_uavAddress)) {
+    //Report a position that is off to one side of where we really are
+    payload.position.x += s_Parameters.attackIntensity * s_Parameters.spawnRadius;
+  }
 
   Address localAddress;
   m_socket->GetSockName (localAddress);
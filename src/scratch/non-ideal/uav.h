@@ -2,6 +2,7 @@
 
 #include "ns3/udp-echo-helper.h"
 
+#include <random>
 #include <stdint.h>
 #include "ns3/application-container.h"
 #include "ns3/application.h"
@@ -61,6 +62,7 @@ protected:
   void BroadcastPosition();
   void Send();
   void Cyberattack();
+  void EndCyberattack();
 
   void Calculate();
 
@@ -78,6 +80,7 @@ private:
   void HandleRead (Ptr<Socket> socket);
 
   UAVDataType_ m_uavType;
+  UAVDataType_ m_typeBeforeAttack;
   Ipv4Address m_uavAddress;
   Time m_packetInterval;
   Time m_calculateInterval;
@@ -98,6 +101,9 @@ private:
 
   std::map<Ipv4Address, SwarmEntry> m_swarmData;
 
+  /// Decides which packets are lost while the swarm is jammed
+  std::mt19937_64 m_jammingRng;
+
   /// Callbacks for tracing the packet Rx events
   TracedCallback<Ptr<const Packet>> m_rxTrace;
 